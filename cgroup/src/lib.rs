@@ -13,11 +13,16 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+//! CPU and memory limits of the cgroup of the process, for v2 and v1
+//! hierarchies.
+
+use sysinfo::CpuRefreshKind;
+
 /// Get cpu limit by cgroup or return the node cpu cores
 pub fn get_cpu_limit() -> usize {
-    let mut cpu_num = read_cpu_cgroup_v1();
+    let mut cpu_num = read_cpu_cgroup_v2();
     if cpu_num == 0 {
-        cpu_num = read_cpu_cgroup_v2();
+        cpu_num = read_cpu_cgroup_v1();
     }
     if cpu_num > 0 {
         if cpu_num < 100000 {
@@ -26,15 +31,17 @@ pub fn get_cpu_limit() -> usize {
             cpu_num / 100000
         }
     } else {
-        super::cpu::get_cpu_num()
+        let mut system = sysinfo::System::new();
+        system.refresh_cpu_list(CpuRefreshKind::nothing());
+        system.cpus().len()
     }
 }
 
 /// Get memory limit by cgroup or return the node memory size
 pub fn get_memory_limit() -> usize {
-    let mut mem_size = read_memory_cgroup_v1();
+    let mut mem_size = read_memory_cgroup_v2();
     if mem_size == 0 {
-        mem_size = read_memory_cgroup_v2();
+        mem_size = read_memory_cgroup_v1();
     };
     let node_mem_size = {
         let mut system = sysinfo::System::new();
@@ -48,7 +55,8 @@ pub fn get_memory_limit() -> usize {
     }
 }
 
-fn read_cpu_cgroup_v1() -> usize {
+/// Get cpu limit by cgroup v2: if there is no limit, default is: max
+fn read_cpu_cgroup_v2() -> usize {
     if let Ok(val) = std::fs::read_to_string("/sys/fs/cgroup/cpu.max") {
         if !val.is_empty() && !val.to_lowercase().starts_with("max") {
             let columns = val.split(' ').collect::<Vec<&str>>();
@@ -58,7 +66,8 @@ fn read_cpu_cgroup_v1() -> usize {
     0
 }
 
-fn read_memory_cgroup_v1() -> usize {
+/// Get memory limit by cgroup v2: if there is no limit, default is: max
+fn read_memory_cgroup_v2() -> usize {
     if let Ok(val) = std::fs::read_to_string("/sys/fs/cgroup/memory.max") {
         if !val.is_empty() && !val.to_lowercase().starts_with("max") {
             return val.trim_end().parse::<usize>().unwrap_or_default();
@@ -67,8 +76,8 @@ fn read_memory_cgroup_v1() -> usize {
     0
 }
 
-/// Get cpu limit by cgroup v2: if there is no limit, default is: -1
-fn read_cpu_cgroup_v2() -> usize {
+/// Get cpu limit by cgroup v1: if there is no limit, default is: -1
+fn read_cpu_cgroup_v1() -> usize {
     if let Ok(val) = std::fs::read_to_string("/sys/fs/cgroup/cpu/cpu.cfs_quota_us") {
         val.trim().to_string().parse::<usize>().unwrap_or_default()
     } else {
@@ -76,12 +85,27 @@ fn read_cpu_cgroup_v2() -> usize {
     }
 }
 
-/// Get memory limit by cgroup v2: if there is no limit, default is:
+/// Get memory limit by cgroup v1: if there is no limit, default is:
 /// 9223372036854775807
-fn read_memory_cgroup_v2() -> usize {
+fn read_memory_cgroup_v1() -> usize {
     if let Ok(val) = std::fs::read_to_string("/sys/fs/cgroup/memory/memory.limit_in_bytes") {
         val.trim_end().parse::<usize>().unwrap_or_default()
     } else {
         0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_cpu_limit() {
+        assert!(get_cpu_limit() > 0);
+    }
+
+    #[test]
+    fn test_get_memory_limit() {
+        assert!(get_memory_limit() > 0);
+    }
+}
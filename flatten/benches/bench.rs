@@ -2,7 +2,7 @@ use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criteri
 use pprof::criterion::{Output, PProfProfiler};
 use std::time::Duration;
 
-use flatten::{v1, v2, v3};
+use flatten::{options::FlattenOptions, stream, v1, v2, v3};
 
 pub fn ben_benchmark(c: &mut Criterion) {
     let mut group: criterion::BenchmarkGroup<'_, criterion::measurement::WallTime> =
@@ -43,10 +43,114 @@ pub fn ben_benchmark(c: &mut Criterion) {
     }
 }
 
+/// Container log shipped by a kubernetes log collector.
+const K8S_LOG: &str = r#"
+{
+    "@timestamp": "2023-11-02T10:15:30.123456Z",
+    "stream": "stdout",
+    "log": "10.244.1.1 - - [02/Nov/2023:10:15:30 +0000] \"GET /api/v1/users?page=2 HTTP/1.1\" 200 5123",
+    "kubernetes": {
+        "pod_name": "web-frontend-7d9f8b6c5d-x2k4p",
+        "namespace_name": "production",
+        "pod_id": "5f3a9c1e-8b2d-4e6f-a7c3-9d1b2e4f6a8c",
+        "host": "ip-10-0-12-34.ec2.internal",
+        "container_name": "nginx",
+        "docker_id": "8f2e4a6c1b3d5e7f9a0b2c4d6e8f0a1b3c5d7e9f",
+        "container_image": "nginx:1.25.3",
+        "labels": {
+            "app.kubernetes.io/name": "web-frontend",
+            "app.kubernetes.io/version": "2.14.1",
+            "pod-template-hash": "7d9f8b6c5d",
+            "tier": "frontend"
+        },
+        "annotations": {
+            "prometheus.io/scrape": "true",
+            "prometheus.io/port": "9113"
+        }
+    },
+    "http": {
+        "method": "GET",
+        "path": "/api/v1/users",
+        "query": {"page": 2, "pageSize": 50},
+        "status": 200,
+        "bytesSent": 5123,
+        "latencyMs": 12.74,
+        "remoteAddr": "10.244.1.1",
+        "headers": {
+            "userAgent": "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36",
+            "accept": ["application/json", "text/plain"]
+        }
+    }
+}"#;
+
+/// Span exported by an OpenTelemetry SDK.
+const OTEL_SPAN: &str = r#"
+{
+    "traceId": "5b8efff798038103d269b633813fc60c",
+    "spanId": "eee19b7ec3c1b174",
+    "parentSpanId": "eee19b7ec3c1b173",
+    "name": "SELECT orders",
+    "kind": 3,
+    "startTimeUnixNano": 1698920130123456789,
+    "endTimeUnixNano": 1698920130135456789,
+    "resource": {
+        "service": {"name": "order-service", "version": "1.8.0", "instance": {"id": "order-7c4f"}},
+        "host": {"name": "node-3", "arch": "amd64"},
+        "telemetry": {"sdk": {"name": "opentelemetry", "language": "go", "version": "1.19.0"}}
+    },
+    "attributes": {
+        "db": {"system": "postgresql", "name": "orders", "statement": "SELECT * FROM orders WHERE customer_id = $1 LIMIT 100", "user": "app"},
+        "net": {"peer": {"name": "orders-db.internal", "port": 5432}},
+        "thread": {"id": 42, "name": "worker-7"}
+    },
+    "events": [
+        {"name": "connection.acquired", "timeUnixNano": 1698920130124000000},
+        {"name": "rows.fetched", "timeUnixNano": 1698920130135000000, "attributes": {"rows": 87}}
+    ],
+    "status": {"code": 1, "message": ""}
+}"#;
+
+/// Compares parsing then flattening with every version to flattening
+/// straight from the JSON text.
+pub fn nested_log_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flatten_nested_log");
+    group.measurement_time(Duration::from_secs(8));
+    let opts = FlattenOptions::default();
+    for (payload, json) in [("k8s_log", K8S_LOG), ("otel_span", OTEL_SPAN)] {
+        let json = json.as_bytes();
+        for alias in ["flatten_lib", "v0.7.2", "v_next"] {
+            let h = match alias {
+                "flatten_lib" => v1::flatten,
+                "v0.7.2" => v2::flatten,
+                "v_next" => v3::flatten,
+                _ => panic!("not support version"),
+            };
+            group.bench_function(
+                BenchmarkId::from_parameter(format!("{payload}-{alias}")),
+                |b| {
+                    b.iter(|| {
+                        let value: serde_json::Value =
+                            serde_json::from_slice(black_box(json)).unwrap();
+                        let _ = h(value);
+                    })
+                },
+            );
+        }
+        group.bench_function(
+            BenchmarkId::from_parameter(format!("{payload}-stream")),
+            |b| {
+                b.iter(|| {
+                    let _ = stream::flatten_slice(black_box(json), &opts);
+                })
+            },
+        );
+    }
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)));
-    targets = ben_benchmark
+    targets = ben_benchmark, nested_log_benchmark
 }
 
 criterion_main!(benches);
@@ -0,0 +1,253 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Flattening batches of records, like the lines of an NDJSON body, in
+//! parallel. A record which fails is reported with its line instead of
+//! failing the batch.
+
+use std::{fmt, sync::Arc};
+
+use arrow::record_batch::RecordBatch;
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use serde_json::Value;
+
+use crate::{
+    options::{ArrayMode, FlattenOptions},
+    record_batch, stream, v3,
+};
+
+/// A record of a batch which couldn't be flattened.
+#[derive(Debug)]
+pub struct LineError {
+    /// 1-based line of the record in NDJSON, its position in a list of
+    /// values.
+    pub line: usize,
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+/// Result of flattening a batch.
+#[derive(Debug, Default)]
+pub struct BatchOutput {
+    /// Flattened records in the order of the input, the records exploded
+    /// from a line follow each other.
+    pub records: Vec<Value>,
+    /// Failed lines, ordered.
+    pub errors: Vec<LineError>,
+}
+
+impl BatchOutput {
+    fn collect(results: Vec<(usize, Result<Vec<Value>, anyhow::Error>)>) -> BatchOutput {
+        let mut output = BatchOutput {
+            records: Vec::with_capacity(results.len()),
+            errors: Vec::new(),
+        };
+        for (line, result) in results {
+            match result {
+                Ok(records) => output.records.extend(records),
+                Err(error) => output.errors.push(LineError { line, error }),
+            }
+        }
+        output
+    }
+
+    /// Converts the flattened records to a [`RecordBatch`] with an inferred
+    /// schema, see [`record_batch::to_record_batch`].
+    ///
+    /// # Errors
+    /// Will return `Err` if a record isn't an object.
+    pub fn to_record_batch(&self) -> Result<RecordBatch, anyhow::Error> {
+        record_batch::to_record_batch(&self.records)
+    }
+}
+
+/// Flattens batches of records with [`FlattenOptions`] on a thread pool,
+/// rayon's global pool unless one is set.
+#[derive(Clone, Default)]
+pub struct BatchFlattener {
+    opts: FlattenOptions,
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl BatchFlattener {
+    pub fn new(opts: FlattenOptions) -> BatchFlattener {
+        BatchFlattener { opts, pool: None }
+    }
+
+    /// Runs on a pool of its own with `threads` threads.
+    pub fn with_threads(self, threads: usize) -> Result<BatchFlattener, anyhow::Error> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("flatten-{i}"))
+            .build()?;
+        Ok(self.with_pool(Arc::new(pool)))
+    }
+
+    /// Runs on `pool`, which may be shared with other work.
+    pub fn with_pool(mut self, pool: Arc<ThreadPool>) -> BatchFlattener {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn options(&self) -> &FlattenOptions {
+        &self.opts
+    }
+
+    /// Flattens every non-blank line of `ndjson` as a JSON object. Lines end
+    /// with `\n` or `\r\n`.
+    pub fn flatten_ndjson(&self, ndjson: &[u8]) -> BatchOutput {
+        let lines = ndjson
+            .split(|&b| b == b'\n')
+            .enumerate()
+            .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+            .collect::<Vec<_>>();
+        let results = self.install(|| {
+            lines
+                .into_par_iter()
+                .map(|(i, line)| (i + 1, self.flatten_line(line)))
+                .collect()
+        });
+        BatchOutput::collect(results)
+    }
+
+    /// Flattens every value of `values`.
+    pub fn flatten_values(&self, values: Vec<Value>) -> BatchOutput {
+        let results = self.install(|| {
+            values
+                .into_par_iter()
+                .enumerate()
+                .map(|(i, v)| (i + 1, v3::flatten_records(v, &self.opts)))
+                .collect()
+        });
+        BatchOutput::collect(results)
+    }
+
+    fn flatten_line(&self, line: &[u8]) -> Result<Vec<Value>, anyhow::Error> {
+        if self.opts.array_mode == ArrayMode::Explode {
+            let v = serde_json::from_slice(line)?;
+            return v3::flatten_records(v, &self.opts);
+        }
+        stream::flatten_slice(line, &self.opts).map(|v| vec![v])
+    }
+
+    fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::v3::KeyCollision;
+
+    fn ndjson(n: usize) -> String {
+        (0..n)
+            .map(|i| format!(r#"{{"seq": {i}, "msg": {{"Text": "line {i}", "tags": [{i}]}}}}"#))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn keeps_order() {
+        let batch = BatchFlattener::default().with_threads(4).unwrap();
+        let output = batch.flatten_ndjson(ndjson(10_000).as_bytes());
+        assert!(output.errors.is_empty());
+        assert_eq!(output.records.len(), 10_000);
+        for (i, record) in output.records.iter().enumerate() {
+            assert_eq!(
+                record,
+                &json!({"seq": i, "msg_text": format!("line {i}"), "msg_tags": format!("[{i}]")})
+            );
+        }
+    }
+
+    #[test]
+    fn line_errors() {
+        let body = concat!(
+            "{\"a\": 1}\r\n",
+            "\n",
+            "{\"a\": \n",
+            "  \r\n",
+            "[1, 2]\n",
+            "{\"b\": {\"c\": 1}, \"b_c\": 2}\n",
+            "{\"a\": 2}",
+        );
+        let output = BatchFlattener::default().flatten_ndjson(body.as_bytes());
+        assert_eq!(output.records, vec![json!({"a": 1}), json!({"a": 2})]);
+        assert_eq!(
+            output.errors.iter().map(|e| e.line).collect::<Vec<_>>(),
+            vec![3, 5, 6]
+        );
+        assert!(output.errors[0].to_string().starts_with("line 3: EOF"));
+        assert_eq!(
+            output.errors[1].to_string(),
+            "line 5: flatten value must be an object at line 1 column 1"
+        );
+        assert!(output.errors[2].error.is::<KeyCollision>());
+    }
+
+    #[test]
+    fn values() {
+        let batch = BatchFlattener::new(FlattenOptions::new().with_array_mode(ArrayMode::Explode));
+        let output = batch.flatten_values(vec![
+            json!({"a": [1, 2]}),
+            json!(3),
+            json!({"b": {"c": [true]}}),
+        ]);
+        assert_eq!(
+            output.records,
+            vec![json!({"a": 1}), json!({"a": 2}), json!({"b_c": true})]
+        );
+        assert_eq!(output.errors.len(), 1);
+        assert_eq!(output.errors[0].line, 2);
+
+        let output = batch.flatten_ndjson(b"{\"a\": [1, 2]}\n{\"a\": [3]}\n{");
+        assert_eq!(
+            output.records,
+            vec![json!({"a": 1}), json!({"a": 2}), json!({"a": 3})]
+        );
+        assert_eq!(output.errors[0].line, 3);
+    }
+
+    #[test]
+    fn shared_pool() {
+        let pool = Arc::new(ThreadPoolBuilder::new().num_threads(2).build().unwrap());
+        let batches = [
+            BatchFlattener::default().with_pool(pool.clone()),
+            BatchFlattener::new(FlattenOptions::new().with_separator(".")).with_pool(pool),
+        ];
+        let body = ndjson(100);
+        let outputs = batches
+            .iter()
+            .map(|batch| batch.flatten_ndjson(body.as_bytes()))
+            .collect::<Vec<_>>();
+        assert_eq!(outputs[0].records[99]["msg_text"], "line 99");
+        assert_eq!(outputs[1].records[99]["msg.text"], "line 99");
+        assert!(BatchFlattener::default()
+            .flatten_ndjson(b"")
+            .records
+            .is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::{borrow::Cow, collections::HashMap, fmt};
+
+use serde_json::value::{Map, Value};
+
+use crate::{
+    options::{Limits, OnCollision, OnLimit},
+    unflatten::{KeyPath, KeyPaths, PathSegment},
+};
+
+/// Two fields of the input which were flattened to the same key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyCollision {
+    pub key: String,
+    /// Paths of the fields in the input, like `a.b[0]`, in the order they
+    /// were flattened.
+    pub paths: Vec<String>,
+}
+
+impl fmt::Display for KeyCollision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "key {} collides after flattening: {}",
+            self.key,
+            self.paths.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for KeyCollision {}
+
+/// Step of the path from the top of the input to a field.
+pub(crate) enum Segment<'a> {
+    Key(Cow<'a, str>),
+    Index(usize),
+}
+
+fn path_string(path: &[Segment]) -> String {
+    let mut s = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(k) => {
+                if !s.is_empty() {
+                    s.push('.');
+                }
+                s.push_str(k);
+            }
+            Segment::Index(i) => {
+                s.push('[');
+                s.push_str(&i.to_string());
+                s.push(']');
+            }
+        }
+    }
+    s
+}
+
+fn key_path(path: &[Segment], stringified: bool) -> KeyPath {
+    KeyPath {
+        path: path
+            .iter()
+            .map(|segment| match segment {
+                Segment::Key(k) => PathSegment::Key(k.to_string()),
+                Segment::Index(i) => PathSegment::Index(*i),
+            })
+            .collect(),
+        stringified,
+    }
+}
+
+/// Collects the flattened fields, resolving keys which are taken already as
+/// set by [`OnCollision`] and enforcing the [`Limits`] on fields and keys.
+pub(crate) struct Flattened {
+    pub map: Map<String, Value>,
+    pub collisions: Vec<KeyCollision>,
+    on_collision: OnCollision,
+    limits: Limits,
+    /// Joins a key to the number of [`OnCollision::Suffix`].
+    separator: String,
+    /// Paths of the fields which aren't just their key, a top level field
+    /// with a valid key is the common case and isn't recorded.
+    origins: HashMap<String, String>,
+    /// Recorded on request, for the same fields plus stringified ones.
+    pub paths: Option<KeyPaths>,
+}
+
+impl Flattened {
+    pub fn new(on_collision: OnCollision, limits: Limits, separator: &str) -> Flattened {
+        Flattened {
+            map: Map::new(),
+            collisions: Vec::new(),
+            on_collision,
+            limits,
+            separator: separator.to_string(),
+            origins: HashMap::new(),
+            paths: None,
+        }
+    }
+
+    pub fn with_paths(mut self) -> Flattened {
+        self.paths = Some(KeyPaths::default());
+        self
+    }
+
+    pub fn insert(
+        &mut self,
+        key: String,
+        value: Value,
+        path: &[Segment],
+    ) -> Result<(), anyhow::Error> {
+        self.put(key, value, path, false)
+    }
+
+    /// Inserts an array or object stringified as `value`.
+    pub fn insert_stringified(
+        &mut self,
+        key: String,
+        value: Value,
+        path: &[Segment],
+    ) -> Result<(), anyhow::Error> {
+        self.put(key, value, path, true)
+    }
+
+    fn put(
+        &mut self,
+        mut key: String,
+        value: Value,
+        path: &[Segment],
+        stringified: bool,
+    ) -> Result<(), anyhow::Error> {
+        if let Some(max) = self.limits.max_key_len {
+            if let Some((end, _)) = key.char_indices().nth(max) {
+                if self.limits.on_limit == OnLimit::Error {
+                    return Err(anyhow::anyhow!(
+                        "key {} of {} is longer than {max} characters",
+                        &key[..end],
+                        path_string(path)
+                    ));
+                }
+                key.truncate(end);
+            }
+        }
+        if !self.map.contains_key(&key) {
+            if self.is_full(path)? {
+                return Ok(());
+            }
+            self.record(key, value, path, stringified);
+            return Ok(());
+        }
+        let first = match self.origins.get(&key) {
+            Some(origin) => origin.clone(),
+            None => key.clone(),
+        };
+        let collision = KeyCollision {
+            key,
+            paths: vec![first, path_string(path)],
+        };
+        match self.on_collision {
+            OnCollision::Error => return Err(collision.into()),
+            OnCollision::KeepFirst => {}
+            OnCollision::KeepLast => self.record(collision.key.clone(), value, path, stringified),
+            OnCollision::Suffix => {
+                let key = self.suffixed(&collision.key, path)?;
+                if let Some(key) = key {
+                    if !self.is_full(path)? {
+                        self.record(key, value, path, stringified);
+                    }
+                }
+            }
+        }
+        self.collisions.push(collision);
+        Ok(())
+    }
+
+    /// Returns the first free key of `key` joined to 1, 2, ... which fits in
+    /// [`Limits::max_key_len`], cutting `key` to keep the number with
+    /// [`OnLimit::Truncate`]. Returns `None` if not even the number fits.
+    fn suffixed(&self, key: &str, path: &[Segment]) -> Result<Option<String>, anyhow::Error> {
+        for n in 1.. {
+            let suffix = format!("{}{n}", self.separator);
+            let mut end = key.len();
+            if let Some(max) = self.limits.max_key_len {
+                let suffix_len = suffix.chars().count();
+                let len = key.chars().count() + suffix_len;
+                if len > max {
+                    if self.limits.on_limit == OnLimit::Error {
+                        return Err(anyhow::anyhow!(
+                            "key {key}{suffix} of {} is longer than {max} characters",
+                            path_string(path)
+                        ));
+                    }
+                    if suffix_len > max {
+                        return Ok(None);
+                    }
+                    end = key
+                        .char_indices()
+                        .nth(max - suffix_len)
+                        .map_or(key.len(), |(i, _)| i);
+                }
+            }
+            let suffixed = format!("{}{suffix}", &key[..end]);
+            if !self.map.contains_key(&suffixed) {
+                return Ok(Some(suffixed));
+            }
+        }
+        unreachable!("a suffix is free")
+    }
+
+    /// Returns `true` if no more fields fit and they are dropped.
+    fn is_full(&self, path: &[Segment]) -> Result<bool, anyhow::Error> {
+        let Some(max) = self.limits.max_fields else {
+            return Ok(false);
+        };
+        if self.map.len() < max {
+            return Ok(false);
+        }
+        match self.limits.on_limit {
+            OnLimit::Error => Err(anyhow::anyhow!(
+                "more than {max} fields after flattening, at {}",
+                path_string(path)
+            )),
+            OnLimit::Truncate => Ok(true),
+        }
+    }
+
+    fn record(&mut self, key: String, value: Value, path: &[Segment], stringified: bool) {
+        let trivial = matches!(path, [Segment::Key(k)] if *k == key);
+        if trivial {
+            self.origins.remove(&key);
+        } else {
+            self.origins.insert(key.clone(), path_string(path));
+        }
+        if let Some(paths) = self.paths.as_mut() {
+            if trivial && !stringified {
+                paths.remove(&key);
+            } else {
+                paths.insert(key.clone(), key_path(path, stringified));
+            }
+        }
+        self.map.insert(key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_string() {
+        let path = [
+            Segment::Key("a".into()),
+            Segment::Index(0),
+            Segment::Index(2),
+            Segment::Key("b.c".into()),
+        ];
+        assert_eq!(path_string(&path), "a[0][2].b.c");
+        assert_eq!(path_string(&[]), "");
+    }
+}
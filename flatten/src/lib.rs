@@ -1,3 +1,10 @@
+pub mod batch;
+pub mod collision;
+pub mod options;
+pub mod record_batch;
+pub mod rules;
+pub mod stream;
+pub mod unflatten;
 pub mod v1;
 pub mod v2;
 pub mod v3; 
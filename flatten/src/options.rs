@@ -0,0 +1,353 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::borrow::Cow;
+
+use crate::rules::{FieldRules, Pattern};
+
+pub const DEFAULT_SEPARATOR: &str = "_";
+pub const DEFAULT_REPLACEMENT: char = '_';
+/// Deepest nesting `serde_json` parses, deeper values only come from
+/// building them in memory.
+pub const DEFAULT_MAX_DEPTH: u32 = 128;
+
+/// How the letters of a key are cased.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyCase {
+    /// Keys keep their case.
+    Preserve,
+    /// `firstName` becomes `firstname`.
+    Lower,
+    /// `firstName` becomes `first_name`, `HTTPServer` becomes `http_server`.
+    Snake,
+}
+
+/// How arrays are flattened. Empty arrays are dropped in every mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ArrayMode {
+    /// `{"a": [1, 2]}` becomes `{"a": "[1,2]"}`.
+    #[default]
+    Stringify,
+    /// `{"a": [1, {"b": 2}]}` becomes `{"a_0": 1, "a_1_b": 2}`.
+    Expand,
+    /// Arrays are kept as JSON arrays, their elements are not flattened.
+    Keep,
+    /// Every element becomes a record of its own, see
+    /// [`crate::v3::flatten_records`]. Several arrays in an object give the
+    /// cartesian product of their elements.
+    Explode,
+}
+
+/// What happens when two fields are flattened to the same key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OnCollision {
+    /// Flattening fails with a [`crate::collision::KeyCollision`].
+    #[default]
+    Error,
+    /// The value flattened first is kept.
+    KeepFirst,
+    /// The value flattened last is kept.
+    KeepLast,
+    /// Later values get the first free key of `key_1`, `key_2`, ... joined
+    /// with the separator, within [`Limits::max_key_len`].
+    Suffix,
+}
+
+/// What happens when the output would exceed [`Limits::max_fields`] or
+/// [`Limits::max_records`], or a key [`Limits::max_key_len`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OnLimit {
+    /// Flattening fails.
+    #[default]
+    Error,
+    /// Fields and records beyond the limit are dropped, keys are cut to the
+    /// limit.
+    Truncate,
+}
+
+/// Bounds on the work and output of flattening a single object, only
+/// [`DEFAULT_MAX_DEPTH`] by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Objects and arrays nested deeper are stringified, keys are made of at
+    /// most `max_depth` parts. Stringifying doesn't recurse, `None` lets
+    /// flattening recurse as deep as the input.
+    pub max_depth: Option<u32>,
+    pub max_fields: Option<usize>,
+    /// Length of a flattened key in characters.
+    pub max_key_len: Option<usize>,
+    /// Records given by [`ArrayMode::Explode`], which multiplies the records
+    /// by the length of every array.
+    pub max_records: Option<usize>,
+    pub on_limit: OnLimit,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_fields: None,
+            max_key_len: None,
+            max_records: None,
+            on_limit: OnLimit::default(),
+        }
+    }
+}
+
+impl Limits {
+    /// Returns `true` if the objects and arrays at `depth` are stringified.
+    pub fn stops_at(&self, depth: u32) -> bool {
+        depth > 0 && self.max_depth.is_some_and(|max| depth >= max)
+    }
+
+    /// Returns `true` if a flat object with these keys stays within the
+    /// limits on fields and keys.
+    pub fn allows<'a>(&self, mut keys: impl ExactSizeIterator<Item = &'a String>) -> bool {
+        if self.max_fields.is_some_and(|max| keys.len() > max) {
+            return false;
+        }
+        match self.max_key_len {
+            Some(max) => keys.all(|k| k.len() <= max || k.chars().count() <= max),
+            None => true,
+        }
+    }
+}
+
+/// Characters kept in keys, every other character is replaced.
+#[derive(Clone, Copy, Debug)]
+pub enum AllowedChars {
+    /// Letters which have a case, digits and `_`.
+    Alphanumeric,
+    /// ASCII letters, digits and `_`.
+    Ascii,
+    /// Every character is kept.
+    Any,
+    /// Characters for which the function returns `true`.
+    Custom(fn(char) -> bool),
+}
+
+impl AllowedChars {
+    pub fn allows(&self, c: char) -> bool {
+        match self {
+            AllowedChars::Alphanumeric => {
+                c.is_lowercase() || c.is_uppercase() || c.is_numeric() || c == '_'
+            }
+            AllowedChars::Ascii => c.is_ascii_alphanumeric() || c == '_',
+            AllowedChars::Any => true,
+            AllowedChars::Custom(f) => f(c),
+        }
+    }
+}
+
+/// Settings of [`crate::v3::flatten_with`].
+///
+/// The default joins keys with `_`, lowercases them and replaces every
+/// character but letters, digits and `_` with `_`, like
+/// [`crate::v3::flatten`].
+#[derive(Clone, Debug)]
+pub struct FlattenOptions {
+    pub separator: String,
+    pub case: KeyCase,
+    pub allowed_chars: AllowedChars,
+    pub replacement: char,
+    pub array_mode: ArrayMode,
+    pub on_collision: OnCollision,
+    pub limits: Limits,
+    pub rules: FieldRules,
+}
+
+impl Default for FlattenOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlattenOptions {
+    pub fn new() -> FlattenOptions {
+        FlattenOptions {
+            separator: DEFAULT_SEPARATOR.to_string(),
+            case: KeyCase::Lower,
+            allowed_chars: AllowedChars::Alphanumeric,
+            replacement: DEFAULT_REPLACEMENT,
+            array_mode: ArrayMode::default(),
+            on_collision: OnCollision::default(),
+            limits: Limits::default(),
+            rules: FieldRules::default(),
+        }
+    }
+
+    pub fn with_separator(mut self, separator: &str) -> FlattenOptions {
+        self.separator = separator.to_string();
+        self
+    }
+
+    pub fn with_case(mut self, case: KeyCase) -> FlattenOptions {
+        self.case = case;
+        self
+    }
+
+    pub fn with_allowed_chars(mut self, allowed_chars: AllowedChars) -> FlattenOptions {
+        self.allowed_chars = allowed_chars;
+        self
+    }
+
+    pub fn with_replacement(mut self, replacement: char) -> FlattenOptions {
+        self.replacement = replacement;
+        self
+    }
+
+    pub fn with_array_mode(mut self, array_mode: ArrayMode) -> FlattenOptions {
+        self.array_mode = array_mode;
+        self
+    }
+
+    pub fn with_on_collision(mut self, on_collision: OnCollision) -> FlattenOptions {
+        self.on_collision = on_collision;
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: u32) -> FlattenOptions {
+        self.limits.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn with_max_fields(mut self, max_fields: usize) -> FlattenOptions {
+        self.limits.max_fields = Some(max_fields);
+        self
+    }
+
+    pub fn with_max_key_len(mut self, max_key_len: usize) -> FlattenOptions {
+        self.limits.max_key_len = Some(max_key_len);
+        self
+    }
+
+    pub fn with_max_records(mut self, max_records: usize) -> FlattenOptions {
+        self.limits.max_records = Some(max_records);
+        self
+    }
+
+    pub fn with_on_limit(mut self, on_limit: OnLimit) -> FlattenOptions {
+        self.limits.on_limit = on_limit;
+        self
+    }
+
+    pub fn with_include(mut self, pattern: Pattern) -> FlattenOptions {
+        self.rules.include.push(pattern);
+        self
+    }
+
+    pub fn with_exclude(mut self, pattern: Pattern) -> FlattenOptions {
+        self.rules.exclude.push(pattern);
+        self
+    }
+
+    /// Renames the field flattened to `key`, see [`FieldRules`].
+    pub fn with_rename(mut self, key: &str, renamed: &str) -> FlattenOptions {
+        self.rules
+            .rename
+            .insert(key.to_string(), renamed.to_string());
+        self
+    }
+
+    /// Returns `true` if `format_key` would change `key`.
+    pub fn needs_format(&self, key: &str) -> bool {
+        key.chars().any(|c| {
+            !self.allowed_chars.allows(c) || (self.case != KeyCase::Preserve && c.is_uppercase())
+        })
+    }
+
+    /// Formats a single key, without allocating when it is already valid.
+    pub fn format_key<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        if !self.needs_format(key) {
+            return Cow::Borrowed(key);
+        }
+        let mut formatted = String::with_capacity(key.len());
+        let mut prev: Option<char> = None;
+        let mut chars = key.chars().peekable();
+        while let Some(c) = chars.next() {
+            if !self.allowed_chars.allows(c) {
+                formatted.push(self.replacement);
+            } else if !c.is_uppercase() || self.case == KeyCase::Preserve {
+                formatted.push(c);
+            } else {
+                if self.case == KeyCase::Snake {
+                    // a word starts after a lowercase letter or a digit, or
+                    // at the last capital of an acronym followed by a word
+                    let boundary = match prev {
+                        Some(p) if p.is_lowercase() || p.is_numeric() => true,
+                        Some(p) if p.is_uppercase() => {
+                            chars.peek().is_some_and(|n| n.is_lowercase())
+                        }
+                        _ => false,
+                    };
+                    if boundary && !formatted.ends_with('_') {
+                        formatted.push('_');
+                    }
+                }
+                formatted.push(c.to_lowercase().next().unwrap());
+            }
+            prev = Some(c);
+        }
+        Cow::Owned(formatted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_format_key() {
+        let opts = FlattenOptions::default();
+        assert!(matches!(opts.format_key("my_key_1"), Cow::Borrowed(_)));
+        assert_eq!(opts.format_key("Hello.World!"), "hello_world_");
+        assert_eq!(opts.format_key("@nested-key"), "_nested_key");
+        assert_eq!(opts.format_key("Größe"), "größe");
+    }
+
+    #[test]
+    fn test_case_modes() {
+        let preserve = FlattenOptions::new().with_case(KeyCase::Preserve);
+        assert!(matches!(preserve.format_key("firstName"), Cow::Borrowed(_)));
+        assert_eq!(preserve.format_key("first.Name"), "first_Name");
+
+        let snake = FlattenOptions::new().with_case(KeyCase::Snake);
+        for (key, expected) in [
+            ("firstName", "first_name"),
+            ("FirstName", "first_name"),
+            ("HTTPServer", "http_server"),
+            ("userID", "user_id"),
+            ("version2Beta", "version2_beta"),
+            ("already_Snake", "already_snake"),
+            ("A.B", "a_b"),
+        ] {
+            assert_eq!(snake.format_key(key), expected, "{key}");
+        }
+    }
+
+    #[test]
+    fn test_allowed_chars() {
+        let ascii = FlattenOptions::new().with_allowed_chars(AllowedChars::Ascii);
+        assert_eq!(ascii.format_key("Größe"), "gr__e");
+
+        let any = FlattenOptions::new().with_allowed_chars(AllowedChars::Any);
+        assert_eq!(any.format_key("Key.With Space"), "key.with space");
+
+        let dots = FlattenOptions::new()
+            .with_allowed_chars(AllowedChars::Custom(|c| c.is_alphanumeric() || c == '.'))
+            .with_replacement('-');
+        assert_eq!(dots.format_key("a.b c_d"), "a.b-c-d");
+    }
+}
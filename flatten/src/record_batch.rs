@@ -0,0 +1,381 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Conversion of flattened records to an Arrow [`RecordBatch`], with a
+//! schema inferred from the records or validated against a given one.
+
+use std::{collections::HashMap, sync::Arc};
+
+use arrow::{
+    array::{
+        ArrayRef, BooleanBuilder, Float32Builder, Float64Builder, Int32Builder, Int64Builder,
+        LargeStringBuilder, NullArray, StringBuilder, UInt64Builder,
+    },
+    record_batch::{RecordBatch, RecordBatchOptions},
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use serde_json::{Map, Value};
+
+/// Type of a column seen so far, ordered by how wide it is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Null,
+    Bool,
+    Int,
+    Float,
+    Utf8,
+}
+
+impl Kind {
+    fn of(v: &Value) -> Kind {
+        match v {
+            Value::Null => Kind::Null,
+            Value::Bool(_) => Kind::Bool,
+            Value::Number(n) if n.is_i64() => Kind::Int,
+            Value::Number(_) => Kind::Float,
+            Value::String(_) | Value::Array(_) | Value::Object(_) => Kind::Utf8,
+        }
+    }
+
+    /// Narrowest kind holding the values of both.
+    fn widen(self, other: Kind) -> Kind {
+        match (self, other) {
+            (k, Kind::Null) | (Kind::Null, k) => k,
+            (a, b) if a == b => a,
+            (Kind::Int, Kind::Float) | (Kind::Float, Kind::Int) => Kind::Float,
+            _ => Kind::Utf8,
+        }
+    }
+
+    fn data_type(self) -> DataType {
+        match self {
+            Kind::Null => DataType::Null,
+            Kind::Bool => DataType::Boolean,
+            Kind::Int => DataType::Int64,
+            Kind::Float => DataType::Float64,
+            Kind::Utf8 => DataType::Utf8,
+        }
+    }
+}
+
+/// Infers the schema of flattened `records`: fields in the order they first
+/// appear, integers widened to floats and conflicting types to strings. A
+/// field is nullable if a record lacks it or holds `null`, a field which is
+/// always `null` has the type [`DataType::Null`].
+///
+/// # Errors
+/// Will return `Err` if a record isn't an object.
+pub fn infer_schema(records: &[Value]) -> Result<Schema, anyhow::Error> {
+    let mut columns: Vec<(&str, Kind, bool)> = Vec::new();
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for (i, record) in records.iter().enumerate() {
+        let record = as_record(i, record)?;
+        for (k, v) in record.iter() {
+            let kind = Kind::of(v);
+            match index.get(k.as_str()) {
+                Some(&pos) => {
+                    let column = &mut columns[pos];
+                    column.1 = column.1.widen(kind);
+                    column.2 |= v.is_null();
+                }
+                None => {
+                    index.insert(k, columns.len());
+                    // missing from the records before
+                    columns.push((k, kind, i > 0 || v.is_null()));
+                }
+            }
+        }
+        if record.len() < columns.len() {
+            for column in columns.iter_mut() {
+                if !record.contains_key(column.0) {
+                    column.2 = true;
+                }
+            }
+        }
+    }
+    Ok(Schema::new(
+        columns
+            .into_iter()
+            .map(|(name, kind, nullable)| Field::new(name, kind.data_type(), nullable))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Converts flattened `records` to a [`RecordBatch`] with the schema
+/// inferred by [`infer_schema`]. Values of a string column which aren't
+/// strings are written as JSON.
+///
+/// # Errors
+/// Will return `Err` if a record isn't an object.
+pub fn to_record_batch(records: &[Value]) -> Result<RecordBatch, anyhow::Error> {
+    let schema = Arc::new(infer_schema(records)?);
+    build(records, schema)
+}
+
+/// Converts flattened `records` to a [`RecordBatch`] of `schema`.
+///
+/// Supported types are [`DataType::Null`], [`DataType::Boolean`],
+/// [`DataType::Int32`], [`DataType::Int64`], [`DataType::UInt64`],
+/// [`DataType::Float32`], [`DataType::Float64`], [`DataType::Utf8`] and
+/// [`DataType::LargeUtf8`]. String columns take any value, non-strings are
+/// written as JSON.
+///
+/// # Errors
+/// Will return `Err` naming the record and field if a record isn't an object,
+/// has a field missing from `schema`, a value doesn't fit the type of its
+/// field or a non-nullable field is missing or `null`.
+pub fn to_record_batch_with_schema(
+    records: &[Value],
+    schema: SchemaRef,
+) -> Result<RecordBatch, anyhow::Error> {
+    for (i, record) in records.iter().enumerate() {
+        let record = as_record(i, record)?;
+        if let Some(k) = record.keys().find(|k| schema.field_with_name(k).is_err()) {
+            return Err(anyhow::anyhow!(
+                "record {i}: field {k} is not in the schema"
+            ));
+        }
+    }
+    build(records, schema)
+}
+
+fn as_record(i: usize, record: &Value) -> Result<&Map<String, Value>, anyhow::Error> {
+    record
+        .as_object()
+        .ok_or_else(|| anyhow::anyhow!("record {i}: flattened record must be an object"))
+}
+
+fn build(records: &[Value], schema: SchemaRef) -> Result<RecordBatch, anyhow::Error> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| build_column(records, field))
+        .collect::<Result<Vec<_>, _>>()?;
+    let options = RecordBatchOptions::new().with_row_count(Some(records.len()));
+    Ok(RecordBatch::try_new_with_options(
+        schema, columns, &options,
+    )?)
+}
+
+/// Appends the value of `field` of every record to a builder of its type,
+/// `$value` converts a non-null JSON value to what the builder takes.
+macro_rules! build_column {
+    ($records:expr, $field:expr, $builder:ty, $value:expr) => {{
+        let mut builder = <$builder>::new();
+        for (i, record) in $records.iter().enumerate() {
+            match record.get($field.name()) {
+                None | Some(Value::Null) => {
+                    check_nullable(i, $field)?;
+                    builder.append_null();
+                }
+                Some(v) => match $value(v) {
+                    Some(v) => builder.append_value(v),
+                    None => return Err(mismatch(i, $field, v)),
+                },
+            }
+        }
+        Arc::new(builder.finish()) as ArrayRef
+    }};
+}
+
+fn build_column(records: &[Value], field: &Field) -> Result<ArrayRef, anyhow::Error> {
+    let column = match field.data_type() {
+        DataType::Null => {
+            for (i, record) in records.iter().enumerate() {
+                match record.get(field.name()) {
+                    None | Some(Value::Null) => {}
+                    Some(v) => return Err(mismatch(i, field, v)),
+                }
+            }
+            Arc::new(NullArray::new(records.len())) as ArrayRef
+        }
+        DataType::Boolean => build_column!(records, field, BooleanBuilder, Value::as_bool),
+        DataType::Int32 => build_column!(records, field, Int32Builder, |v: &Value| v
+            .as_i64()
+            .and_then(|v| i32::try_from(v).ok())),
+        DataType::Int64 => build_column!(records, field, Int64Builder, Value::as_i64),
+        DataType::UInt64 => build_column!(records, field, UInt64Builder, Value::as_u64),
+        DataType::Float32 => build_column!(records, field, Float32Builder, |v: &Value| v
+            .as_f64()
+            .map(|v| v as f32)),
+        DataType::Float64 => build_column!(records, field, Float64Builder, Value::as_f64),
+        DataType::Utf8 => build_column!(records, field, StringBuilder, |v| Some(json_text(v))),
+        DataType::LargeUtf8 => {
+            build_column!(records, field, LargeStringBuilder, |v| Some(json_text(v)))
+        }
+        other => {
+            return Err(anyhow::anyhow!(
+                "field {}: unsupported type {other}",
+                field.name()
+            ))
+        }
+    };
+    Ok(column)
+}
+
+/// Text of `v` in a string column, strings without their quotes.
+fn json_text(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn check_nullable(i: usize, field: &Field) -> Result<(), anyhow::Error> {
+    if field.is_nullable() {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "record {i}: field {} is not nullable",
+        field.name()
+    ))
+}
+
+fn mismatch(i: usize, field: &Field, v: &Value) -> anyhow::Error {
+    anyhow::anyhow!(
+        "record {i}: value {v} of field {} is not {}",
+        field.name(),
+        field.data_type()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Array, AsArray};
+    use arrow::datatypes::{Float64Type, Int64Type};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        batch::BatchFlattener,
+        options::{ArrayMode, FlattenOptions},
+    };
+
+    #[test]
+    fn infer() {
+        let records = [
+            json!({"a": 1, "b": 1, "c": true, "d": "x", "e": null}),
+            json!({"a": 2, "b": 2.5, "c": 1, "f": [1]}),
+            json!({"a": 3, "b": null, "c": false, "d": "y", "e": null}),
+        ];
+        let schema = infer_schema(&records).unwrap();
+        assert_eq!(
+            schema,
+            Schema::new(vec![
+                Field::new("a", DataType::Int64, false),
+                Field::new("b", DataType::Float64, true),
+                Field::new("c", DataType::Utf8, false),
+                Field::new("d", DataType::Utf8, true),
+                Field::new("e", DataType::Null, true),
+                Field::new("f", DataType::Utf8, true),
+            ])
+        );
+
+        let batch = to_record_batch(&records).unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(
+            batch.column(0).as_primitive::<Int64Type>().values(),
+            &[1, 2, 3]
+        );
+        let b = batch.column(1).as_primitive::<Float64Type>();
+        assert_eq!((b.value(0), b.value(1), b.is_null(2)), (1.0, 2.5, true));
+        let c = batch.column(2).as_string::<i32>();
+        assert_eq!((c.value(0), c.value(1), c.value(2)), ("true", "1", "false"));
+        assert!(batch.column(3).is_null(1));
+        assert_eq!(batch.column(4).len(), 3);
+        assert_eq!(batch.column(5).as_string::<i32>().value(1), "[1]");
+
+        let batch = to_record_batch(&[]).unwrap();
+        assert_eq!((batch.num_rows(), batch.num_columns()), (0, 0));
+        assert!(to_record_batch(&[json!([1])]).is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("status", DataType::Int32, false),
+            Field::new("latency", DataType::Float32, true),
+            Field::new("msg", DataType::LargeUtf8, true),
+            Field::new("ok", DataType::Boolean, true),
+        ]));
+        let records = [
+            json!({"status": 200, "latency": 1, "msg": "a"}),
+            json!({"status": 404, "latency": 2.5, "ok": false, "msg": {"k": 1}}),
+        ];
+        let batch = to_record_batch_with_schema(&records, schema.clone()).unwrap();
+        assert_eq!(batch.schema(), schema);
+        assert_eq!(batch.column(2).as_string::<i64>().value(1), "{\"k\":1}");
+        assert!(batch.column(3).is_null(0));
+
+        for (records, error) in [
+            (
+                vec![json!({"status": 200}), json!({"latency": 1.0})],
+                "record 1: field status is not nullable",
+            ),
+            (
+                vec![json!({"status": 200}), json!({"status": null})],
+                "record 1: field status is not nullable",
+            ),
+            (
+                vec![json!({"status": "200"})],
+                "record 0: value \"200\" of field status is not Int32",
+            ),
+            (
+                vec![json!({"status": 3_000_000_000u64})],
+                "record 0: value 3000000000 of field status is not Int32",
+            ),
+            (
+                vec![json!({"status": 200, "ok": 1})],
+                "record 0: value 1 of field ok is not Boolean",
+            ),
+            (
+                vec![json!({"status": 200, "host": "a"})],
+                "record 0: field host is not in the schema",
+            ),
+        ] {
+            let err = to_record_batch_with_schema(&records, schema.clone()).unwrap_err();
+            assert_eq!(err.to_string(), error);
+        }
+
+        let schema = Arc::new(Schema::new(vec![Field::new("t", DataType::Date32, true)]));
+        assert!(to_record_batch_with_schema(&[json!({})], schema).is_err());
+    }
+
+    #[test]
+    fn flattened_ndjson() {
+        let body = concat!(
+            r#"{"ts": 1, "http": {"status": 200, "latency": 3}, "tags": ["a"]}"#,
+            "\n",
+            r#"{"ts": 2, "http": {"status": 500, "latency": 2.5}, "error": {"kind": "io"}}"#,
+        );
+        let batch = BatchFlattener::new(FlattenOptions::new().with_array_mode(ArrayMode::Expand));
+        let output = batch.flatten_ndjson(body.as_bytes());
+        let batch = output.to_record_batch().unwrap();
+        assert_eq!(
+            batch.schema().as_ref(),
+            &Schema::new(vec![
+                Field::new("http_latency", DataType::Float64, false),
+                Field::new("http_status", DataType::Int64, false),
+                Field::new("tags_0", DataType::Utf8, true),
+                Field::new("ts", DataType::Int64, false),
+                Field::new("error_kind", DataType::Utf8, true),
+            ])
+        );
+        assert_eq!(
+            batch.column(1).as_primitive::<Int64Type>().values(),
+            &[200, 500]
+        );
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Rules choosing and renaming the fields of flattened objects. They are
+//! checked while flattening, the fields of an excluded object are never
+//! visited.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+/// Pattern matched against a flattened key.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// Matches the whole key, `*` matching any characters and `?` a single
+    /// one.
+    Glob(String),
+    /// Matches if the regex finds a match in the key, anchor it to match the
+    /// whole key.
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn glob(pattern: &str) -> Pattern {
+        Pattern::Glob(pattern.to_string())
+    }
+
+    /// # Errors
+    /// Will return `Err` if `pattern` isn't a valid regex.
+    pub fn regex(pattern: &str) -> Result<Pattern, anyhow::Error> {
+        Ok(Pattern::Regex(Regex::new(pattern)?))
+    }
+
+    pub fn matches(&self, key: &str) -> bool {
+        match self {
+            Pattern::Glob(glob) => glob_matches(glob.as_bytes(), key),
+            Pattern::Regex(re) => re.is_match(key),
+        }
+    }
+}
+
+/// Matches `key` against `glob`, backtracking to the last `*` on a mismatch.
+fn glob_matches(glob: &[u8], key: &str) -> bool {
+    let bytes = key.as_bytes();
+    let (mut g, mut k) = (0, 0);
+    // position after the last `*` and of the key it is matched up to
+    let mut star: Option<(usize, usize)> = None;
+    while k < bytes.len() {
+        match glob.get(g) {
+            Some(b'*') => {
+                g += 1;
+                star = Some((g, k));
+            }
+            Some(b'?') => {
+                g += 1;
+                k += char_len(key, k);
+            }
+            Some(&c) if c == bytes[k] => {
+                g += 1;
+                k += 1;
+            }
+            _ => match star {
+                Some((after, matched)) => {
+                    let matched = matched + char_len(key, matched);
+                    star = Some((after, matched));
+                    g = after;
+                    k = matched;
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == b'*')
+}
+
+fn char_len(key: &str, at: usize) -> usize {
+    key[at..].chars().next().map_or(1, char::len_utf8)
+}
+
+/// Fields kept and renamed by flattening, all of them as they are by
+/// default.
+///
+/// Rules are matched against the flattened key of every field of an object,
+/// objects included, before its value is visited:
+/// - a field matching an exclude pattern is dropped along with its fields;
+/// - with include patterns, a field which isn't an object is only kept if
+///   it or one of the objects it is nested in matches one of them;
+/// - a field whose key is in `rename` gets the new key, which the keys of
+///   its fields start with and are matched as.
+///
+/// Arrays are matched as a whole, like values.
+#[derive(Clone, Debug, Default)]
+pub struct FieldRules {
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+    pub rename: HashMap<String, String>,
+}
+
+impl FieldRules {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty() && self.rename.is_empty()
+    }
+
+    /// Returns `true` if every field is included without matching an include
+    /// pattern.
+    pub fn includes_all(&self) -> bool {
+        self.include.is_empty()
+    }
+
+    pub fn includes(&self, key: &str) -> bool {
+        self.include.iter().any(|p| p.matches(key))
+    }
+
+    pub fn excludes(&self, key: &str) -> bool {
+        self.exclude.iter().any(|p| p.matches(key))
+    }
+
+    /// Returns the new key of the field `key`.
+    pub fn rename(&self, key: String) -> String {
+        match self.rename.get(&key) {
+            Some(renamed) => renamed.clone(),
+            None => key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob() {
+        for (glob, key, matches) in [
+            (
+                "kubernetes_annotations_*",
+                "kubernetes_annotations_app",
+                true,
+            ),
+            ("kubernetes_annotations_*", "kubernetes_annotations", false),
+            ("*_id", "trace_id", true),
+            ("*_id", "trace_id_x", false),
+            ("a*b*c", "a_b_b_c", true),
+            ("a*b*c", "a_c_b", false),
+            ("?_x", "é_x", true),
+            ("größe_?", "größe_1", true),
+            ("**", "", true),
+            ("", "a", false),
+            ("log", "log", true),
+        ] {
+            assert_eq!(Pattern::glob(glob).matches(key), matches, "{glob} {key}");
+        }
+    }
+
+    #[test]
+    fn regex() {
+        let re = Pattern::regex("^http_(status|method)$").unwrap();
+        assert!(re.matches("http_status"));
+        assert!(!re.matches("http_status_text"));
+        assert!(Pattern::regex("_id").unwrap().matches("span_id_hex"));
+        assert!(Pattern::regex("(").is_err());
+    }
+}
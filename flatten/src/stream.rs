@@ -0,0 +1,483 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Flattening straight from JSON text, without parsing it to a
+//! [`serde_json::Value`] first. Fields are emitted while the input is read,
+//! only arrays and objects which are stringified are built as values.
+
+use std::{borrow::Cow, fmt};
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::value::{Map, Value};
+
+use crate::{
+    collision::{Flattened, Segment},
+    options::{ArrayMode, FlattenOptions},
+};
+
+/// Receives the fields of [`flatten_slice_into`].
+pub trait FlatSink {
+    fn insert(&mut self, key: String, value: Value) -> Result<(), anyhow::Error>;
+}
+
+/// Later fields overwrite earlier ones with the same key.
+impl FlatSink for Map<String, Value> {
+    fn insert(&mut self, key: String, value: Value) -> Result<(), anyhow::Error> {
+        Map::insert(self, key, value);
+        Ok(())
+    }
+}
+
+impl FlatSink for Vec<(String, Value)> {
+    fn insert(&mut self, key: String, value: Value) -> Result<(), anyhow::Error> {
+        self.push((key, value));
+        Ok(())
+    }
+}
+
+/// Flattens the JSON object in `json` like [`crate::v3::flatten_with`].
+///
+/// The fields are flattened in the order of the input instead of the order
+/// of the keys, which matters to the resolution of collisions. Duplicated
+/// keys in the input collide instead of the last one winning.
+///
+/// # Errors
+/// Will return `Err` if `json` isn't a JSON object, if two keys collide with
+/// [`crate::options::OnCollision::Error`], if the output exceeds the limits
+/// with [`crate::options::OnLimit::Error`] or with
+/// [`ArrayMode::Explode`].
+pub fn flatten_slice(json: &[u8], opts: &FlattenOptions) -> Result<Value, anyhow::Error> {
+    let mut flat = Flattened::new(opts.on_collision, opts.limits, &opts.separator);
+    run(json, opts, &mut flat)?;
+    Ok(Value::Object(flat.map))
+}
+
+/// Flattens the JSON object in `json` into `sink`, which gets every field in
+/// the order of the input. Keys are formatted and deeper values stringified
+/// as set in `opts`, collisions and the number of fields are up to the sink.
+///
+/// # Errors
+/// Will return `Err` if `json` isn't a JSON object, if the sink fails or
+/// with [`ArrayMode::Explode`].
+pub fn flatten_slice_into<S: FlatSink>(
+    json: &[u8],
+    opts: &FlattenOptions,
+    sink: &mut S,
+) -> Result<(), anyhow::Error> {
+    run(json, opts, &mut SinkEmit(sink))
+}
+
+/// Destination of the flattened fields along with their path in the input.
+trait Emit {
+    fn emit(
+        &mut self,
+        key: String,
+        value: Value,
+        path: &[Segment],
+        stringified: bool,
+    ) -> Result<(), anyhow::Error>;
+}
+
+impl Emit for Flattened {
+    fn emit(
+        &mut self,
+        key: String,
+        value: Value,
+        path: &[Segment],
+        stringified: bool,
+    ) -> Result<(), anyhow::Error> {
+        if stringified {
+            self.insert_stringified(key, value, path)
+        } else {
+            self.insert(key, value, path)
+        }
+    }
+}
+
+struct SinkEmit<'s, S>(&'s mut S);
+
+impl<S: FlatSink> Emit for SinkEmit<'_, S> {
+    fn emit(
+        &mut self,
+        key: String,
+        value: Value,
+        _path: &[Segment],
+        _stringified: bool,
+    ) -> Result<(), anyhow::Error> {
+        self.0.insert(key, value)
+    }
+}
+
+fn run<E: Emit>(json: &[u8], opts: &FlattenOptions, out: &mut E) -> Result<(), anyhow::Error> {
+    if opts.array_mode == ArrayMode::Explode {
+        return Err(anyhow::anyhow!(
+            "exploding arrays gives several records, use v3::flatten_records"
+        ));
+    }
+    let mut flattener = Flattener {
+        opts,
+        path: Vec::new(),
+        out,
+        failed: None,
+    };
+    let mut de = serde_json::Deserializer::from_slice(json);
+    let res = FieldSeed {
+        f: &mut flattener,
+        key: String::new(),
+        depth: 0,
+        included: opts.rules.includes_all(),
+    }
+    .deserialize(&mut de)
+    .and_then(|()| de.end());
+    if let Some(e) = flattener.failed.take() {
+        return Err(e);
+    }
+    res.map_err(Into::into)
+}
+
+struct Flattener<'de, 'a, E> {
+    opts: &'a FlattenOptions,
+    /// Keys leading to the value being read, borrowed from the input unless
+    /// they hold escapes.
+    path: Vec<Segment<'de>>,
+    out: &'a mut E,
+    /// Error of `out`, the deserializer only carries a message.
+    failed: Option<anyhow::Error>,
+}
+
+impl<E: Emit> Flattener<'_, '_, E> {
+    fn emit<Err: de::Error>(
+        &mut self,
+        key: String,
+        value: Value,
+        stringified: bool,
+    ) -> Result<(), Err> {
+        self.out
+            .emit(key, value, &self.path, stringified)
+            .map_err(|e| {
+                let err = Err::custom(&e);
+                self.failed = Some(e);
+                err
+            })
+    }
+}
+
+/// Reads the value of the field `key`, at `depth` like in `v3`. Unless
+/// `included`, only the fields of objects matching an include rule are
+/// emitted.
+struct FieldSeed<'f, 'de, 'a, E> {
+    f: &'f mut Flattener<'de, 'a, E>,
+    key: String,
+    depth: u32,
+    included: bool,
+}
+
+impl<'de, E: Emit> DeserializeSeed<'de> for FieldSeed<'_, 'de, '_, E> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<E: Emit> FieldSeed<'_, '_, '_, E> {
+    fn scalar<Err: de::Error>(self, value: Value) -> Result<(), Err> {
+        if self.depth == 0 {
+            return Err(Err::custom("flatten value must be an object"));
+        }
+        if !self.included {
+            return Ok(());
+        }
+        self.f.emit(self.key, value, false)
+    }
+}
+
+impl<'de, E: Emit> Visitor<'de> for FieldSeed<'_, 'de, '_, E> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_bool<Err: de::Error>(self, v: bool) -> Result<(), Err> {
+        self.scalar(Value::Bool(v))
+    }
+
+    fn visit_i64<Err: de::Error>(self, v: i64) -> Result<(), Err> {
+        self.scalar(Value::from(v))
+    }
+
+    fn visit_u64<Err: de::Error>(self, v: u64) -> Result<(), Err> {
+        self.scalar(Value::from(v))
+    }
+
+    fn visit_f64<Err: de::Error>(self, v: f64) -> Result<(), Err> {
+        self.scalar(Value::from(v))
+    }
+
+    fn visit_str<Err: de::Error>(self, v: &str) -> Result<(), Err> {
+        self.scalar(Value::String(v.to_string()))
+    }
+
+    fn visit_string<Err: de::Error>(self, v: String) -> Result<(), Err> {
+        self.scalar(Value::String(v))
+    }
+
+    fn visit_unit<Err: de::Error>(self) -> Result<(), Err> {
+        self.scalar(Value::Null)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let opts = self.f.opts;
+        if opts.limits.stops_at(self.depth) {
+            if !self.included {
+                while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+                return Ok(());
+            }
+            let mut obj = Map::new();
+            while let Some((k, v)) = map.next_entry::<String, Value>()? {
+                obj.insert(k, v);
+            }
+            if obj.is_empty() {
+                return Ok(());
+            }
+            let v = Value::String(Value::Object(obj).to_string());
+            return self.f.emit(self.key, v, true);
+        }
+        while let Some(k) = map.next_key_seed(KeySeed)? {
+            let key = if self.depth > 0 {
+                format!("{}{}{}", self.key, opts.separator, opts.format_key(&k))
+            } else {
+                opts.format_key(&k).into_owned()
+            };
+            if opts.rules.excludes(&key) {
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            }
+            let included = self.included || opts.rules.includes(&key);
+            self.f.path.push(Segment::Key(k));
+            map.next_value_seed(FieldSeed {
+                f: &mut *self.f,
+                key: opts.rules.rename(key),
+                depth: self.depth + 1,
+                included,
+            })?;
+            self.f.path.pop();
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        if self.depth == 0 {
+            return Err(de::Error::custom("flatten value must be an object"));
+        }
+        if !self.included {
+            while seq.next_element::<IgnoredAny>()?.is_some() {}
+            return Ok(());
+        }
+        let opts = self.f.opts;
+        let stops = opts.limits.stops_at(self.depth);
+        if opts.array_mode == ArrayMode::Expand && !stops {
+            for i in 0.. {
+                let key = format!("{}{}{}", self.key, opts.separator, i);
+                self.f.path.push(Segment::Index(i));
+                let next = seq.next_element_seed(FieldSeed {
+                    f: &mut *self.f,
+                    key,
+                    depth: self.depth + 1,
+                    included: true,
+                })?;
+                self.f.path.pop();
+                if next.is_none() {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+        let mut arr = Vec::new();
+        while let Some(v) = seq.next_element::<Value>()? {
+            arr.push(v);
+        }
+        if arr.is_empty() {
+            return Ok(());
+        }
+        if opts.array_mode == ArrayMode::Keep && !stops {
+            return self.f.emit(self.key, Value::Array(arr), false);
+        }
+        let v = Value::String(Value::Array(arr).to_string());
+        self.f.emit(self.key, v, true)
+    }
+}
+
+/// Reads a key, borrowing it from the input when it has no escapes.
+struct KeySeed;
+
+impl<'de> DeserializeSeed<'de> for KeySeed {
+    type Value = Cow<'de, str>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for KeySeed {
+    type Value = Cow<'de, str>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a key")
+    }
+
+    fn visit_borrowed_str<Err: de::Error>(self, v: &'de str) -> Result<Self::Value, Err> {
+        Ok(Cow::Borrowed(v))
+    }
+
+    fn visit_str<Err: de::Error>(self, v: &str) -> Result<Self::Value, Err> {
+        Ok(Cow::Owned(v.to_string()))
+    }
+
+    fn visit_string<Err: de::Error>(self, v: String) -> Result<Self::Value, Err> {
+        Ok(Cow::Owned(v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        options::{KeyCase, OnCollision},
+        rules::Pattern,
+        v3::{flatten_with, KeyCollision},
+    };
+
+    const LOG: &str = r#"{
+        "@timestamp": "2023-11-02T10:15:30.123Z",
+        "Level": "info",
+        "message": "GET /api/users 200",
+        "kubernetes": {
+            "pod": {"name": "web-7d9f", "uid": "a1b2"},
+            "labels": {"app.kubernetes.io/name": "web", "tier": "frontend"},
+            "containers": [{"name": "web", "restarts": 0}, {"name": "sidecar"}]
+        },
+        "http": {"status": 200, "latencyMs": 12.5, "tags": [], "headers": {}},
+        "user": null,
+        "escaped\"key": "x"
+    }"#;
+
+    #[test]
+    fn same_as_v3() {
+        let doc: Value = serde_json::from_str(LOG).unwrap();
+        for opts in [
+            FlattenOptions::new(),
+            FlattenOptions::new().with_array_mode(ArrayMode::Expand),
+            FlattenOptions::new().with_array_mode(ArrayMode::Keep),
+            FlattenOptions::new()
+                .with_case(KeyCase::Snake)
+                .with_separator("."),
+            FlattenOptions::new()
+                .with_max_depth(2)
+                .with_array_mode(ArrayMode::Expand),
+            FlattenOptions::new()
+                .with_max_depth(1)
+                .with_array_mode(ArrayMode::Keep),
+            FlattenOptions::new()
+                .with_exclude(Pattern::glob("kubernetes_labels"))
+                .with_exclude(Pattern::glob("*_uid"))
+                .with_rename("kubernetes_pod", "pod"),
+            FlattenOptions::new()
+                .with_include(Pattern::glob("kubernetes_pod"))
+                .with_include(Pattern::glob("kubernetes_containers"))
+                .with_include(Pattern::glob("http_*"))
+                .with_array_mode(ArrayMode::Expand),
+            FlattenOptions::new()
+                .with_include(Pattern::glob("kubernetes"))
+                .with_max_depth(2),
+        ] {
+            assert_eq!(
+                flatten_slice(LOG.as_bytes(), &opts).unwrap(),
+                flatten_with(doc.clone(), &opts).unwrap(),
+                "{opts:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn sink() {
+        let mut fields = Vec::new();
+        flatten_slice_into(
+            br#"{"b": {"Y": 1, "x": [1, 2]}, "a": true, "b_y": 2}"#,
+            &FlattenOptions::new(),
+            &mut fields,
+        )
+        .unwrap();
+        assert_eq!(
+            fields,
+            vec![
+                ("b_y".to_string(), json!(1)),
+                ("b_x".to_string(), json!("[1,2]")),
+                ("a".to_string(), json!(true)),
+                ("b_y".to_string(), json!(2)),
+            ]
+        );
+
+        let mut map = Map::new();
+        flatten_slice_into(
+            br#"{"a": {"b": 1}, "a_b": 2}"#,
+            &FlattenOptions::new(),
+            &mut map,
+        )
+        .unwrap();
+        assert_eq!(Value::Object(map), json!({"a_b": 2}));
+    }
+
+    #[test]
+    fn collisions_in_input_order() {
+        let json = br#"{"a_b": 1, "a": {"b": 2}}"#;
+        let err = flatten_slice(json, &FlattenOptions::new()).unwrap_err();
+        let collision = err.downcast_ref::<KeyCollision>().unwrap();
+        assert_eq!(collision.paths, vec!["a_b", "a.b"]);
+
+        let opts = FlattenOptions::new().with_on_collision(OnCollision::KeepFirst);
+        assert_eq!(flatten_slice(json, &opts).unwrap(), json!({"a_b": 1}));
+
+        // duplicated keys
+        let json = br#"{"a": 1, "a": 2}"#;
+        assert!(flatten_slice(json, &FlattenOptions::new()).is_err());
+        let opts = FlattenOptions::new().with_on_collision(OnCollision::KeepLast);
+        assert_eq!(flatten_slice(json, &opts).unwrap(), json!({"a": 2}));
+    }
+
+    #[test]
+    fn invalid_input() {
+        let opts = FlattenOptions::new();
+        for json in [
+            "3",
+            "\"a\"",
+            "null",
+            "[1, 2]",
+            "{\"a\": 1",
+            "{\"a\": 1} x",
+            "",
+        ] {
+            assert!(flatten_slice(json.as_bytes(), &opts).is_err(), "{json}");
+        }
+        assert_eq!(flatten_slice(b"{}", &opts).unwrap(), json!({}));
+        assert_eq!(flatten_slice(b" {} \n", &opts).unwrap(), json!({}));
+
+        let opts = FlattenOptions::new().with_array_mode(ArrayMode::Explode);
+        assert!(flatten_slice(b"{}", &opts).is_err());
+    }
+}
@@ -0,0 +1,321 @@
+// Copyright 2023 Zinc Labs Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::{Map, Value};
+
+use crate::options::DEFAULT_SEPARATOR;
+
+/// Step of the path from the top of a document to a field.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Where a flattened field came from.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyPath {
+    pub path: Vec<PathSegment>,
+    /// The value is an array or object stringified by flattening.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stringified: bool,
+}
+
+/// Original paths of the keys of a flattened object, recorded by
+/// [`crate::v3::flatten_with_paths`] to reverse it exactly.
+///
+/// Top level fields whose key was kept as is aren't recorded.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct KeyPaths {
+    paths: HashMap<String, KeyPath>,
+}
+
+impl KeyPaths {
+    pub fn get(&self, key: &str) -> Option<&KeyPath> {
+        self.paths.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub(crate) fn insert(&mut self, key: String, path: KeyPath) {
+        self.paths.insert(key, path);
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) {
+        self.paths.remove(key);
+    }
+}
+
+/// Settings of [`unflatten`].
+#[derive(Clone, Debug)]
+pub struct UnflattenOptions {
+    pub separator: String,
+    /// Strings holding a JSON array are parsed back into arrays.
+    pub parse_arrays: bool,
+}
+
+impl Default for UnflattenOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UnflattenOptions {
+    pub fn new() -> UnflattenOptions {
+        UnflattenOptions {
+            separator: DEFAULT_SEPARATOR.to_string(),
+            parse_arrays: false,
+        }
+    }
+
+    pub fn with_separator(mut self, separator: &str) -> UnflattenOptions {
+        self.separator = separator.to_string();
+        self
+    }
+
+    pub fn with_parse_arrays(mut self, parse_arrays: bool) -> UnflattenOptions {
+        self.parse_arrays = parse_arrays;
+        self
+    }
+}
+
+/// Rebuilds nested objects from a flattened object by splitting its keys at
+/// the separator, `{"a_b": 1}` becomes `{"a": {"b": 1}}`.
+///
+/// Splitting can't tell a separator from the same characters in a key, and
+/// formatted keys stay formatted, use [`unflatten_with_paths`] to reverse
+/// flattening exactly. Keys which can't be split because a shorter key holds
+/// a value, like `a_b` next to `a`, are kept as they are.
+///
+/// # Errors
+/// Will return `Err` if `flat` it's not an object.
+pub fn unflatten(flat: Value, opts: &UnflattenOptions) -> Result<Value, anyhow::Error> {
+    let Value::Object(flat) = flat else {
+        return Err(anyhow::anyhow!("unflatten value must be an object"));
+    };
+    let mut nested = Value::Object(Map::with_capacity(flat.len()));
+    for (k, v) in flat.into_iter() {
+        let v = if opts.parse_arrays { parse_array(v) } else { v };
+        let path = k
+            .split(opts.separator.as_str())
+            .map(|part| PathSegment::Key(part.to_string()))
+            .collect::<Vec<_>>();
+        if let Err(v) = place(&mut nested, &path, v) {
+            if let Value::Object(nested) = &mut nested {
+                nested.insert(k, v);
+            }
+        }
+    }
+    Ok(nested)
+}
+
+/// Rebuilds the object flattened along with `paths`. Keys missing from
+/// `paths` were top level keys and are kept as they are.
+///
+/// # Errors
+/// Will return `Err` if `flat` it's not an object or two paths conflict,
+/// which flattening never records.
+pub fn unflatten_with_paths(flat: Value, paths: &KeyPaths) -> Result<Value, anyhow::Error> {
+    let Value::Object(flat) = flat else {
+        return Err(anyhow::anyhow!("unflatten value must be an object"));
+    };
+    let mut nested = Value::Object(Map::with_capacity(flat.len()));
+    for (k, v) in flat.into_iter() {
+        let placed = match paths.get(&k) {
+            Some(origin) => {
+                let v = match v {
+                    Value::String(s) if origin.stringified => {
+                        serde_json::from_str(&s).unwrap_or(Value::String(s))
+                    }
+                    v => v,
+                };
+                place(&mut nested, &origin.path, v)
+            }
+            None => place(&mut nested, &[PathSegment::Key(k.clone())], v),
+        };
+        if placed.is_err() {
+            return Err(anyhow::anyhow!(
+                "path of key {k} conflicts with another key"
+            ));
+        }
+    }
+    Ok(nested)
+}
+
+fn parse_array(v: Value) -> Value {
+    match v {
+        Value::String(s) if s.starts_with('[') && s.ends_with(']') => {
+            match serde_json::from_str(&s) {
+                Ok(arr @ Value::Array(_)) => arr,
+                _ => Value::String(s),
+            }
+        }
+        v => v,
+    }
+}
+
+/// Puts `value` at `path` below `node`, creating the objects and arrays on
+/// the way. Gives `value` back if a field on the way holds another kind of
+/// value.
+fn place(node: &mut Value, path: &[PathSegment], value: Value) -> Result<(), Value> {
+    let Some((first, rest)) = path.split_first() else {
+        if !node.is_null() {
+            return Err(value);
+        }
+        *node = value;
+        return Ok(());
+    };
+    let child = match first {
+        PathSegment::Key(k) => {
+            if node.is_null() {
+                *node = Value::Object(Map::new());
+            }
+            let Value::Object(map) = node else {
+                return Err(value);
+            };
+            map.entry(k.as_str()).or_insert(Value::Null)
+        }
+        PathSegment::Index(i) => {
+            if node.is_null() {
+                *node = Value::Array(Vec::new());
+            }
+            let Value::Array(arr) = node else {
+                return Err(value);
+            };
+            if arr.len() <= *i {
+                arr.resize(i + 1, Value::Null);
+            }
+            &mut arr[*i]
+        }
+    };
+    place(child, rest, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::v3::{flatten_with_paths, ArrayMode, FlattenOptions, KeyCase};
+
+    #[test]
+    fn split_keys() {
+        let flat = json!({"a": 1, "b_c": 2, "b_d_e": "x", "f": "[1,2]"});
+        assert_eq!(
+            unflatten(flat.clone(), &UnflattenOptions::new()).unwrap(),
+            json!({"a": 1, "b": {"c": 2, "d": {"e": "x"}}, "f": "[1,2]"})
+        );
+        let opts = UnflattenOptions::new().with_parse_arrays(true);
+        assert_eq!(
+            unflatten(flat, &opts).unwrap(),
+            json!({"a": 1, "b": {"c": 2, "d": {"e": "x"}}, "f": [1, 2]})
+        );
+
+        let opts = UnflattenOptions::new().with_separator(".");
+        assert_eq!(
+            unflatten(json!({"a.b": 1, "a.c_d": 2}), &opts).unwrap(),
+            json!({"a": {"b": 1, "c_d": 2}})
+        );
+        assert!(unflatten(json!([1]), &opts).is_err());
+    }
+
+    #[test]
+    fn unsplittable_keys() {
+        let flat = json!({"a": 1, "a_b": 2, "c_d": 3, "c_d_e": 4, "f": "[1,"});
+        let opts = UnflattenOptions::new().with_parse_arrays(true);
+        assert_eq!(
+            unflatten(flat, &opts).unwrap(),
+            json!({"a": 1, "a_b": 2, "c": {"d": 3}, "c_d_e": 4, "f": "[1,"})
+        );
+    }
+
+    #[test]
+    fn round_trip_with_paths() {
+        let doc = json!({
+            "A.1": "x",
+            "level": "info",
+            "http": {"Status": 200, "remote_addr": "10.0.0.1"},
+            "tags": ["a", "b"],
+            "spans": [{"name": "db", "ms": 3}, null, {"name": "http"}],
+            "msg": "[not an array]"
+        });
+
+        let (flat, paths) = flatten_with_paths(doc.clone(), &FlattenOptions::new()).unwrap();
+        assert_eq!(flat["a_1"], "x");
+        assert!(paths.get("level").is_none());
+        assert!(paths.get("msg").is_none());
+        assert!(paths.get("tags").unwrap().stringified);
+        assert_eq!(unflatten_with_paths(flat, &paths).unwrap(), doc);
+
+        let opts = FlattenOptions::new()
+            .with_array_mode(ArrayMode::Expand)
+            .with_case(KeyCase::Snake);
+        let (flat, paths) = flatten_with_paths(doc.clone(), &opts).unwrap();
+        assert_eq!(flat["spans_0_name"], "db");
+        assert_eq!(
+            paths.get("spans_2_name").unwrap().path,
+            vec![
+                PathSegment::Key("spans".to_string()),
+                PathSegment::Index(2),
+                PathSegment::Key("name".to_string())
+            ]
+        );
+        assert_eq!(unflatten_with_paths(flat, &paths).unwrap(), doc);
+
+        // subtrees stringified beyond the max depth
+        let doc = json!({"a": {"b": {"c": [1, {"d": 2}]}}});
+        let opts = FlattenOptions::new()
+            .with_max_depth(2)
+            .with_array_mode(ArrayMode::Expand);
+        let (flat, paths) = flatten_with_paths(doc.clone(), &opts).unwrap();
+        assert_eq!(flat, json!({"a_b": "{\"c\":[1,{\"d\":2}]}"}));
+        assert_eq!(unflatten_with_paths(flat, &paths).unwrap(), doc);
+    }
+
+    #[test]
+    fn paths_sidecar_serde() {
+        let doc = json!({"Key": {"list": [1]}, "k": [2]});
+        let opts = FlattenOptions::new().with_array_mode(ArrayMode::Expand);
+        let (flat, paths) = flatten_with_paths(doc.clone(), &opts).unwrap();
+
+        let sidecar = serde_json::to_value(&paths).unwrap();
+        assert_eq!(
+            sidecar,
+            json!({
+                "key_list_0": {"path": ["Key", "list", 0]},
+                "k_0": {"path": ["k", 0]}
+            })
+        );
+        let paths: KeyPaths = serde_json::from_value(sidecar).unwrap();
+        assert_eq!(unflatten_with_paths(flat, &paths).unwrap(), doc);
+
+        let paths: KeyPaths =
+            serde_json::from_value(json!({"a": {"path": ["x"]}, "b": {"path": ["x", "y"]}}))
+                .unwrap();
+        let err = unflatten_with_paths(json!({"a": 1, "b": 2}), &paths).unwrap_err();
+        assert!(err.to_string().contains("key b conflicts"), "{err}");
+    }
+}
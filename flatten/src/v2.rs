@@ -13,9 +13,14 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::borrow::Cow;
+
 use serde_json::value::Map;
 use serde_json::value::Value;
 
+use crate::collision::{Flattened, Segment};
+use crate::options::{Limits, OnCollision};
+
 const KEY_SEPARATOR: &str = "_";
 const FORMAT_KEY_ENABLED: bool = true;
 
@@ -28,17 +33,20 @@ const FORMAT_KEY_ENABLED: bool = true;
 /// Will return `Err` if `to_flatten` it's not an object, or if flattening the object would
 /// result in two or more keys colliding.
 pub fn flatten(to_flatten: Value) -> Result<Value, anyhow::Error> {
-    let mut flat = Map::<String, Value>::new();
-    flatten_value(&to_flatten, "".to_owned(), 0, &mut flat).map(|_x| Value::Object(flat))
+    let mut flat = Flattened::new(OnCollision::Error, Limits::default(), "_");
+    flatten_value(&to_flatten, "".to_owned(), 0, &mut Vec::new(), &mut flat)
+        .map(|_x| Value::Object(flat.map))
 }
 
 /// Flattens the passed JSON value (`current`), whose path is `parent_key` and its 0-based
-/// depth is `depth`.  The result is stored in the JSON object `flattened`.
-fn flatten_value(
-    current: &Value,
+/// depth is `depth`.  The result is stored in `flattened`, `path` holds the original keys
+/// leading to `current`.
+fn flatten_value<'a>(
+    current: &'a Value,
     parent_key: String,
     depth: u32,
-    flattened: &mut Map<String, Value>,
+    path: &mut Vec<Segment<'a>>,
+    flattened: &mut Flattened,
 ) -> Result<(), anyhow::Error> {
     if depth == 0 {
         match current {
@@ -52,46 +60,49 @@ fn flatten_value(
     }
 
     if let Some(current) = current.as_object() {
-        flatten_object(current, &parent_key, depth, flattened)?;
+        flatten_object(current, &parent_key, depth, path, flattened)?;
     } else if let Some(current) = current.as_array() {
-        flatten_array(current, &parent_key, depth, flattened)?;
+        flatten_array(current, &parent_key, path, flattened)?;
     } else {
-        flattened.insert(parent_key, current.clone());
+        flattened.insert(parent_key, current.clone(), path)?;
     }
     Ok(())
 }
 
 /// Flattens the passed object (`current`), whose path is `parent_key` and its 0-based depth
-/// is `depth`.  The result is stored in the JSON object `flattened`.
-fn flatten_object(
-    current: &Map<String, Value>,
+/// is `depth`.  The result is stored in `flattened`.
+fn flatten_object<'a>(
+    current: &'a Map<String, Value>,
     parent_key: &str,
     depth: u32,
-    flattened: &mut Map<String, Value>,
+    path: &mut Vec<Segment<'a>>,
+    flattened: &mut Flattened,
 ) -> Result<(), anyhow::Error> {
-    for (k, v) in current.iter() {
+    for (original, v) in current.iter() {
         let k = if FORMAT_KEY_ENABLED {
-            format_key(k)
+            format_key(original)
         } else {
-            k.to_string()
+            original.to_string()
         };
         let parent_key = if depth > 0 {
-            format!("{}{}{}", parent_key, KEY_SEPARATOR, k)
+            format!("{parent_key}{KEY_SEPARATOR}{k}")
         } else {
             k
         };
-        flatten_value(v, parent_key, depth + 1, flattened)?;
+        path.push(Segment::Key(Cow::Borrowed(original)));
+        flatten_value(v, parent_key, depth + 1, path, flattened)?;
+        path.pop();
     }
     Ok(())
 }
 
-/// Flattens the passed array (`current`), whose path is `parent_key` and its 0-based depth
-/// is `depth`.  The result is stored in the JSON object `flattened`.
+/// Flattens the passed array (`current`), whose path is `parent_key`, to a JSON string.  The
+/// result is stored in `flattened`.
 fn flatten_array(
     current: &[Value],
     parent_key: &str,
-    depth: u32,
-    flattened: &mut Map<String, Value>,
+    path: &mut Vec<Segment<'_>>,
+    flattened: &mut Flattened,
 ) -> Result<(), anyhow::Error> {
     if current.is_empty() {
         return Ok(());
@@ -101,7 +112,7 @@ fn flatten_array(
     //     flatten_value(obj, parent_key, depth + 1, flattened)?;
     // }
     let v = Value::String(Value::Array(current.to_vec()).to_string());
-    flatten_value(&v, parent_key.to_string(), depth, flattened)?;
+    flattened.insert(parent_key.to_string(), v, path)?;
     Ok(())
 }
 
@@ -125,4 +136,33 @@ pub fn format_key(key: &str) -> String {
         })
         .collect::<String>()
 }
- 
\ No newline at end of file
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::collision::KeyCollision;
+
+    #[test]
+    fn nested_object() {
+        let obj = json!({"a": {"B.c": 1, "d": [1, 2]}, "e": "f"});
+        assert_eq!(
+            flatten(obj).unwrap(),
+            json!({"a_b_c": 1, "a_d": "[1,2]", "e": "f"})
+        );
+    }
+
+    #[test]
+    fn colliding_keys() {
+        let obj = json!({"a": {"b": 1}, "a_b": 2});
+        let err = flatten(obj).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<KeyCollision>(),
+            Some(&KeyCollision {
+                key: "a_b".to_string(),
+                paths: vec!["a.b".to_string(), "a_b".to_string()],
+            })
+        );
+    }
+}
@@ -13,9 +13,22 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::{borrow::Cow, sync::LazyLock};
+
 use serde_json::value::{Map, Value};
 
-const KEY_SEPARATOR: &str = "_";
+pub use crate::collision::KeyCollision;
+use crate::collision::{Flattened, Segment};
+pub use crate::options::{
+    AllowedChars, ArrayMode, FlattenOptions, KeyCase, Limits, OnCollision, OnLimit,
+};
+pub use crate::rules::{FieldRules, Pattern};
+pub use crate::unflatten::{
+    unflatten, unflatten_with_paths, KeyPath, KeyPaths, PathSegment, UnflattenOptions,
+};
+
+/// Options of [`flatten`] and [`format_key`], built once.
+static DEFAULT_OPTIONS: LazyLock<FlattenOptions> = LazyLock::new(FlattenOptions::default);
 
 /// Flattens the provided JSON object (`current`).
 ///
@@ -27,19 +40,108 @@ const KEY_SEPARATOR: &str = "_";
 /// Will return `Err` if `to_flatten` it's not an object, or if flattening the
 /// object would result in two or more keys colliding.
 pub fn flatten(to_flatten: Value) -> Result<Value, anyhow::Error> {
+    flatten_with(to_flatten, &DEFAULT_OPTIONS)
+}
+
+/// Flattens `to_flatten` like [`flatten`], joining and formatting the keys as
+/// set in `opts`.
+///
+/// # Errors
+/// Also returns `Err` with [`ArrayMode::Explode`], which may give several
+/// records, use [`flatten_records`] instead.
+pub fn flatten_with(to_flatten: Value, opts: &FlattenOptions) -> Result<Value, anyhow::Error> {
+    flatten_with_collisions(to_flatten, opts).map(|(v, _collisions)| v)
+}
+
+/// Like [`flatten_with`], also returning the collisions resolved by
+/// [`OnCollision::KeepFirst`], [`OnCollision::KeepLast`] or
+/// [`OnCollision::Suffix`].
+pub fn flatten_with_collisions(
+    to_flatten: Value,
+    opts: &FlattenOptions,
+) -> Result<(Value, Vec<KeyCollision>), anyhow::Error> {
+    check_single_record(opts)?;
+    let mut flat = Flattened::new(opts.on_collision, opts.limits, &opts.separator);
+    flatten_into(to_flatten, opts, &mut flat)?;
+    Ok((Value::Object(flat.map), flat.collisions))
+}
+
+/// Like [`flatten_with`], also returning the original paths of the keys for
+/// [`unflatten_with_paths`].
+pub fn flatten_with_paths(
+    to_flatten: Value,
+    opts: &FlattenOptions,
+) -> Result<(Value, KeyPaths), anyhow::Error> {
+    check_single_record(opts)?;
+    let mut flat = Flattened::new(opts.on_collision, opts.limits, &opts.separator).with_paths();
+    flatten_into(to_flatten, opts, &mut flat)?;
+    Ok((Value::Object(flat.map), flat.paths.unwrap_or_default()))
+}
+
+fn check_single_record(opts: &FlattenOptions) -> Result<(), anyhow::Error> {
+    if opts.array_mode == ArrayMode::Explode {
+        return Err(anyhow::anyhow!(
+            "exploding arrays gives several records, use flatten_records"
+        ));
+    }
+    Ok(())
+}
+
+/// Flattens `to_flatten` into one record per element of its arrays with
+/// [`ArrayMode::Explode`], into a single record with the other modes.
+///
+/// # Errors
+/// Will return `Err` if `to_flatten` it's not an object, if two keys
+/// collide with [`OnCollision::Error`] or a record exceeds the [`Limits`]
+/// with [`OnLimit::Error`].
+pub fn flatten_records(
+    to_flatten: Value,
+    opts: &FlattenOptions,
+) -> Result<Vec<Value>, anyhow::Error> {
+    if opts.array_mode != ArrayMode::Explode {
+        return flatten_with(to_flatten, opts).map(|v| vec![v]);
+    }
+    if !to_flatten.is_object() {
+        return Err(anyhow::anyhow!("flatten value must be an object"));
+    }
+    explode(to_flatten, "", 0, opts.rules.includes_all(), opts)?
+        .into_iter()
+        .map(|record| {
+            let mut flat = Flattened::new(opts.on_collision, opts.limits, &opts.separator);
+            flatten_into(record, opts, &mut flat).map(|_x| Value::Object(flat.map))
+        })
+        .collect()
+}
+
+fn flatten_into(
+    to_flatten: Value,
+    opts: &FlattenOptions,
+    flat: &mut Flattened,
+) -> Result<(), anyhow::Error> {
     // quick check to see if we have an object`
     let to_flatten = match to_flatten {
         Value::Object(v) => {
+            if !opts.rules.is_empty() {
+                return flatten_object(
+                    v,
+                    "",
+                    0,
+                    opts.rules.includes_all(),
+                    opts,
+                    &mut Vec::new(),
+                    flat,
+                );
+            }
             if v.is_empty() || !v.iter().any(|(_k, v)| v.is_object() || v.is_array()) {
-                if v.iter().all(|(k, _v)| check_key(k)) {
-                    return Ok(Value::Object(v));
+                if v.iter().all(|(k, _v)| !opts.needs_format(k)) && opts.limits.allows(v.keys()) {
+                    flat.map = v;
+                    return Ok(());
                 }
-                let mut formatted_map = Map::<String, Value>::with_capacity(v.len());
-                for (mut k, v) in v.into_iter() {
-                    format_key(&mut k);
-                    formatted_map.insert(k, v);
+                for (k, v) in v.into_iter() {
+                    let key = opts.format_key(&k).into_owned();
+                    flat.insert(key, v, &[Segment::Key(Cow::Owned(k))])?;
                 }
-                return Ok(Value::Object(formatted_map));
+                return Ok(());
             }
             Value::Object(v)
         }
@@ -48,92 +150,311 @@ pub fn flatten(to_flatten: Value) -> Result<Value, anyhow::Error> {
         }
     };
 
-    let mut flat = Map::<String, Value>::new();
-    flatten_value(to_flatten, "".to_owned(), 0, &mut flat).map(|_x| Value::Object(flat))
+    flatten_value(
+        to_flatten,
+        "".to_owned(),
+        0,
+        true,
+        opts,
+        &mut Vec::new(),
+        flat,
+    )
 }
 
 /// Flattens the passed JSON value (`current`), whose path is `parent_key` and
-/// its 0-based depth is `depth`.  The result is stored in the JSON object
-/// `flattened`.
+/// its 0-based depth is `depth`.  The result is stored in `flattened`, `path`
+/// holds the original keys leading to `current`. The fields of objects are
+/// kept without matching an include rule if `included`.
 fn flatten_value(
     current: Value,
     parent_key: String,
     depth: u32,
-    flattened: &mut Map<String, Value>,
+    included: bool,
+    opts: &FlattenOptions,
+    path: &mut Vec<Segment<'static>>,
+    flattened: &mut Flattened,
 ) -> Result<(), anyhow::Error> {
+    if (current.is_object() || current.is_array()) && opts.limits.stops_at(depth) {
+        let empty = match &current {
+            Value::Object(map) => map.is_empty(),
+            Value::Array(arr) => arr.is_empty(),
+            _ => false,
+        };
+        if !empty {
+            let v = Value::String(into_json_string(current));
+            flattened.insert_stringified(parent_key, v, path)?;
+        }
+        return Ok(());
+    }
     match current {
         Value::Object(map) => {
-            flatten_object(map, &parent_key, depth, flattened)?;
+            flatten_object(map, &parent_key, depth, included, opts, path, flattened)?;
         }
         Value::Array(arr) => {
-            flatten_array(arr, &parent_key, depth, flattened)?;
+            flatten_array(arr, &parent_key, depth, opts, path, flattened)?;
         }
         _ => {
-            flattened.insert(parent_key, current);
+            flattened.insert(parent_key, current, path)?;
         }
     }
     Ok(())
 }
 
 /// Flattens the passed object (`current`), whose path is `parent_key` and its
-/// 0-based depth is `depth`.  The result is stored in the JSON object
-/// `flattened`.
+/// 0-based depth is `depth`.  The result is stored in `flattened`.
 fn flatten_object(
     current: Map<String, Value>,
     parent_key: &str,
     depth: u32,
-    flattened: &mut Map<String, Value>,
+    included: bool,
+    opts: &FlattenOptions,
+    path: &mut Vec<Segment<'static>>,
+    flattened: &mut Flattened,
 ) -> Result<(), anyhow::Error> {
-    for (mut k, v) in current.into_iter() {
-        format_key(&mut k);
-        let parent_key = if depth > 0 {
-            format!("{}{}{}", parent_key, KEY_SEPARATOR, k)
-        } else {
-            k
+    for (k, v) in current.into_iter() {
+        let Some((parent_key, included)) = field_key(parent_key, &k, &v, depth, included, opts)
+        else {
+            drop_deep(v);
+            continue;
         };
-        flatten_value(v, parent_key, depth + 1, flattened)?;
+        path.push(Segment::Key(Cow::Owned(k)));
+        flatten_value(v, parent_key, depth + 1, included, opts, path, flattened)?;
+        path.pop();
     }
     Ok(())
 }
 
+/// Returns the key of the field `k` with the value `v` of an object whose key
+/// is `parent_key`, renamed by the [`FieldRules`], and whether its fields are
+/// included. Returns `None` if the rules drop the field.
+fn field_key(
+    parent_key: &str,
+    k: &str,
+    v: &Value,
+    depth: u32,
+    included: bool,
+    opts: &FlattenOptions,
+) -> Option<(String, bool)> {
+    let key = if depth > 0 {
+        format!("{}{}{}", parent_key, opts.separator, opts.format_key(k))
+    } else {
+        opts.format_key(k).into_owned()
+    };
+    if opts.rules.is_empty() {
+        return Some((key, true));
+    }
+    if opts.rules.excludes(&key) {
+        return None;
+    }
+    let included = included || opts.rules.includes(&key);
+    // objects are visited for the fields matching an include rule
+    let nested = v.is_object() && !opts.limits.stops_at(depth + 1);
+    if !included && !nested {
+        return None;
+    }
+    Some((opts.rules.rename(key), included))
+}
+
 /// Flattens the passed array (`current`), whose path is `parent_key` and its
-/// 0-based depth is `depth`.  The result is stored in the JSON object
-/// `flattened`.
+/// 0-based depth is `depth`.  The result is stored in `flattened`.
 fn flatten_array(
     current: Vec<Value>,
     parent_key: &str,
     depth: u32,
-    flattened: &mut Map<String, Value>,
+    opts: &FlattenOptions,
+    path: &mut Vec<Segment<'static>>,
+    flattened: &mut Flattened,
 ) -> Result<(), anyhow::Error> {
     if current.is_empty() {
         return Ok(());
     }
-    // for (i, obj) in current.iter().enumerate() {
-    //     let parent_key = format!("{}{}{}", parent_key, KEY_SEPARATOR, i);
-    //     flatten_value(obj, parent_key, depth + 1, flattened)?;
-    // }
-    let v = Value::String(Value::Array(current.to_vec()).to_string());
-    flatten_value(v, parent_key.to_string(), depth, flattened)?;
+    match opts.array_mode {
+        // arrays left by `explode` are nested too deep to be exploded
+        ArrayMode::Stringify | ArrayMode::Explode => {
+            let v = Value::String(into_json_string(Value::Array(current)));
+            flattened.insert_stringified(parent_key.to_string(), v, path)?;
+        }
+        ArrayMode::Expand => {
+            for (i, v) in current.into_iter().enumerate() {
+                let parent_key = format!("{}{}{}", parent_key, opts.separator, i);
+                path.push(Segment::Index(i));
+                flatten_value(v, parent_key, depth + 1, true, opts, path, flattened)?;
+                path.pop();
+            }
+        }
+        ArrayMode::Keep => {
+            flattened.insert(parent_key.to_string(), Value::Array(current), path)?;
+        }
+    }
     Ok(())
 }
 
+/// Splits `current` into one value per element of its arrays, several
+/// arrays in an object give the cartesian product of their elements. Arrays
+/// nested in arrays are exploded too, empty arrays are kept. Values deeper
+/// than [`Limits::max_depth`] are left as they are. Fields dropped by the
+/// [`FieldRules`] are dropped before their arrays are exploded, `key` is the
+/// flattened key of `current` they are matched with.
+///
+/// The number of values is checked against [`Limits::max_records`] before
+/// they are built.
+fn explode(
+    current: Value,
+    key: &str,
+    depth: u32,
+    included: bool,
+    opts: &FlattenOptions,
+) -> Result<Vec<Value>, anyhow::Error> {
+    if opts.limits.stops_at(depth) {
+        // stringified now rather than cloned into every record
+        let current = match current {
+            Value::Object(map) if !map.is_empty() => Value::Object(map),
+            Value::Array(arr) if !arr.is_empty() => Value::Array(arr),
+            v => return Ok(vec![v]),
+        };
+        return Ok(vec![Value::String(into_json_string(current))]);
+    }
+    match current {
+        Value::Object(map) => {
+            let mut records = vec![Map::new()];
+            for (k, v) in map.into_iter() {
+                let Some((key, included)) = field_key(key, &k, &v, depth, included, opts) else {
+                    drop_deep(v);
+                    continue;
+                };
+                let mut values = explode(v, &key, depth + 1, included, opts)?;
+                if values.len() == 1 {
+                    let v = values.pop().unwrap();
+                    for record in records.iter_mut() {
+                        record.insert(k.clone(), v.clone());
+                    }
+                    continue;
+                }
+                let len = max_records(records.len().saturating_mul(values.len()), opts)?;
+                records = records
+                    .iter()
+                    .flat_map(|record| {
+                        values.iter().map(|v| {
+                            let mut record = record.clone();
+                            record.insert(k.clone(), v.clone());
+                            record
+                        })
+                    })
+                    .take(len)
+                    .collect();
+            }
+            Ok(records.into_iter().map(Value::Object).collect())
+        }
+        Value::Array(arr) if !arr.is_empty() => {
+            let mut values = Vec::new();
+            for v in arr.into_iter() {
+                let exploded = explode(v, key, depth + 1, included, opts)?;
+                let total = values.len() + exploded.len();
+                let len = max_records(total, opts)?;
+                values.extend(exploded.into_iter().take(len - values.len()));
+                if len < total {
+                    break;
+                }
+            }
+            Ok(values)
+        }
+        _ => Ok(vec![current]),
+    }
+}
+
+/// Returns how many of `len` exploded values are kept, all of them unless
+/// there are more than [`Limits::max_records`].
+fn max_records(len: usize, opts: &FlattenOptions) -> Result<usize, anyhow::Error> {
+    match opts.limits.max_records {
+        Some(max) if len > max => match opts.limits.on_limit {
+            OnLimit::Error => Err(anyhow::anyhow!(
+                "more than {max} records after exploding arrays"
+            )),
+            OnLimit::Truncate => Ok(max),
+        },
+        _ => Ok(len),
+    }
+}
+
+/// Serializes `current` like [`Value::to_string`], keeping the arrays and
+/// objects being written on a stack instead of recursing. The values are
+/// dropped as they are written.
+fn into_json_string(current: Value) -> String {
+    enum Open {
+        Array(std::vec::IntoIter<Value>),
+        Object(serde_json::map::IntoIter),
+    }
+
+    let mut out = Vec::new();
+    let mut open: Vec<(Open, bool)> = Vec::new();
+    let mut next = Some(current);
+    loop {
+        match next.take() {
+            Some(Value::Array(arr)) => {
+                out.push(b'[');
+                open.push((Open::Array(arr.into_iter()), true));
+            }
+            Some(Value::Object(map)) => {
+                out.push(b'{');
+                open.push((Open::Object(map.into_iter()), true));
+            }
+            Some(v) => write_json(&mut out, &v),
+            None => {}
+        }
+        let Some((container, first)) = open.last_mut() else {
+            break;
+        };
+        let item = match container {
+            Open::Array(values) => values.next().map(|v| (None, v)),
+            Open::Object(fields) => fields.next().map(|(k, v)| (Some(k), v)),
+        };
+        match item {
+            Some((k, v)) => {
+                if !std::mem::take(first) {
+                    out.push(b',');
+                }
+                if let Some(k) = k {
+                    write_json(&mut out, &k);
+                    out.push(b':');
+                }
+                next = Some(v);
+            }
+            None => {
+                out.push(match container {
+                    Open::Array(_) => b']',
+                    Open::Object(_) => b'}',
+                });
+                open.pop();
+            }
+        }
+    }
+    String::from_utf8(out).expect("JSON is UTF-8")
+}
+
+/// Writes a string or a scalar, which serialize without recursing.
+fn write_json<T: serde::Serialize + ?Sized>(out: &mut Vec<u8>, v: &T) {
+    serde_json::to_writer(out, v).expect("writing to a Vec doesn't fail");
+}
+
+/// Drops `current` without recursing into its arrays and objects.
+fn drop_deep(current: Value) {
+    let mut values = vec![current];
+    while let Some(v) = values.pop() {
+        match v {
+            Value::Array(arr) => values.extend(arr),
+            Value::Object(map) => values.extend(map.into_iter().map(|(_k, v)| v)),
+            _ => {}
+        }
+    }
+}
+
 /// We need every character in the key to be lowercase alphanumeric or
 /// underscore
 pub fn format_key(key: &mut String) {
     if check_key(key) {
         return;
     }
-    let mut key_chars = key.chars().collect::<Vec<_>>();
-    for c in key_chars.iter_mut() {
-        if c.is_lowercase() || c.is_numeric() {
-            continue;
-        } else if c.is_uppercase() {
-            *c = c.to_lowercase().next().unwrap();
-        } else {
-            *c = '_';
-        }
-    }
-    *key = key_chars.into_iter().collect::<String>();
+    *key = DEFAULT_OPTIONS.format_key(key).into_owned();
 }
 
 fn check_key(key: &str) -> bool {
@@ -146,30 +467,31 @@ mod tests {
     use serde_json::json;
 
     use super::*;
+    use crate::options::DEFAULT_MAX_DEPTH;
 
     #[test]
     fn test_check_key_lowercase() {
-        assert_eq!(check_key("hello"), true);
+        assert!(check_key("hello"));
     }
 
     #[test]
     fn test_check_key_numeric() {
-        assert_eq!(check_key("123"), true);
+        assert!(check_key("123"));
     }
 
     #[test]
     fn test_check_key_underscore() {
-        assert_eq!(check_key("my_key"), true);
+        assert!(check_key("my_key"));
     }
 
     #[test]
     fn test_check_key_mixed_case() {
-        assert_eq!(check_key("Hello_World"), false);
+        assert!(!check_key("Hello_World"));
     }
 
     #[test]
     fn test_check_key_special_characters() {
-        assert_eq!(check_key("key!"), false);
+        assert!(!check_key("key!"));
     }
 
     #[test]
@@ -193,7 +515,7 @@ mod tests {
         assert_eq!(
             flatten(obj).unwrap(),
             json!({
-                format!("s{k}a", k = KEY_SEPARATOR): "[1,2.0,\"b\",null,true]",
+                format!("s{k}a", k = crate::options::DEFAULT_SEPARATOR): "[1,2.0,\"b\",null,true]",
             })
         );
     }
@@ -236,16 +558,17 @@ mod tests {
         );
     }
 
-    // #[test]
-    // fn overlapping_after_flattening_array() {
-    //     let obj = json!({"key": ["value1", "value2"], "key_0": "Oopsy"});
-    //     let res = flatten(&obj);
-    //     assert!(res.is_err());
-    //     match res {
-    //         Err(err) => assert!(err.to_string().contains("key_0")),
-    //         Ok(_) => panic!("This should have failed"),
-    //     }
-    // }
+    #[test]
+    fn overlapping_after_flattening_array() {
+        let obj = json!({"key": ["value1", "value2"], "key_0": "Oopsy"});
+        let opts = FlattenOptions::new().with_array_mode(ArrayMode::Expand);
+        let res = flatten_with(obj, &opts);
+        assert!(res.is_err());
+        match res {
+            Err(err) => assert!(err.to_string().contains("key_0")),
+            Ok(_) => panic!("This should have failed"),
+        }
+    }
 
     /// Ensure that empty arrays are not present in the result
     #[test]
@@ -386,4 +709,513 @@ mod tests {
         let output = flatten(input).unwrap();
         assert_eq!(output, expected_output);
     }
+
+    #[test]
+    fn default_options() {
+        let obj = json!({"Key.1": "a", "nested": {"firstName": "b", "list": [1, 2]}});
+        let expected = json!({"key_1": "a", "nested_firstname": "b", "nested_list": "[1,2]"});
+        assert_eq!(flatten(obj.clone()).unwrap(), expected);
+        assert_eq!(
+            flatten_with(obj, &FlattenOptions::default()).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn custom_separator_and_case() {
+        let obj = json!({"httpRequest": {"statusCode": 200, "remoteIP": "10.0.0.1"}});
+        let opts = FlattenOptions::new()
+            .with_separator(".")
+            .with_case(KeyCase::Snake);
+        assert_eq!(
+            flatten_with(obj.clone(), &opts).unwrap(),
+            json!({"http_request.status_code": 200, "http_request.remote_ip": "10.0.0.1"})
+        );
+
+        let opts = FlattenOptions::new()
+            .with_separator("__")
+            .with_case(KeyCase::Preserve);
+        assert_eq!(
+            flatten_with(obj, &opts).unwrap(),
+            json!({"httpRequest__statusCode": 200, "httpRequest__remoteIP": "10.0.0.1"})
+        );
+    }
+
+    #[test]
+    fn custom_allowed_chars() {
+        let obj = json!({"k8s": {"app.kubernetes.io/name": "web"}});
+        let opts = FlattenOptions::new()
+            .with_separator(".")
+            .with_allowed_chars(AllowedChars::Custom(|c| {
+                c.is_ascii_alphanumeric() || c == '.' || c == '/'
+            }));
+        assert_eq!(
+            flatten_with(obj.clone(), &opts).unwrap(),
+            json!({"k8s.app.kubernetes.io/name": "web"})
+        );
+
+        let opts = FlattenOptions::new().with_replacement('-');
+        assert_eq!(
+            flatten_with(obj, &opts).unwrap(),
+            json!({"k8s_app-kubernetes-io-name": "web"})
+        );
+    }
+
+    #[test]
+    fn fast_path_with_options() {
+        let obj = json!({"Status": 200, "remoteIP": "10.0.0.1"});
+        let opts = FlattenOptions::new().with_case(KeyCase::Preserve);
+        assert_eq!(flatten_with(obj.clone(), &opts).unwrap(), obj);
+        let opts = FlattenOptions::new().with_case(KeyCase::Snake);
+        assert_eq!(
+            flatten_with(obj, &opts).unwrap(),
+            json!({"status": 200, "remote_ip": "10.0.0.1"})
+        );
+    }
+
+    #[test]
+    fn complex_array_modes() {
+        let obj = json!({"a": [1, [2, [3, 4], 5], 6]});
+
+        let opts = FlattenOptions::new().with_array_mode(ArrayMode::Expand);
+        assert_eq!(
+            flatten_with(obj.clone(), &opts).unwrap(),
+            json!({"a_0": 1, "a_1_0": 2, "a_1_1_0": 3, "a_1_1_1": 4, "a_1_2": 5, "a_2": 6})
+        );
+
+        let opts = FlattenOptions::new().with_array_mode(ArrayMode::Keep);
+        assert_eq!(flatten_with(obj.clone(), &opts).unwrap(), obj);
+
+        let opts = FlattenOptions::new().with_array_mode(ArrayMode::Explode);
+        assert!(flatten_with(obj.clone(), &opts).is_err());
+        assert_eq!(
+            flatten_records(obj, &opts).unwrap(),
+            (1..=6).map(|i| json!({ "a": i })).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn complex_nested_struct_modes() {
+        let obj = json!({
+            "simple_key": "simple_value",
+            "key": [
+                "value1",
+                {"key": "value2"},
+                {"nested_array": [
+                    "nested1",
+                    "nested2",
+                    ["nested3", "nested4"]
+                ]}
+            ]
+        });
+
+        let opts = FlattenOptions::new().with_array_mode(ArrayMode::Expand);
+        assert_eq!(
+            flatten_with(obj.clone(), &opts).unwrap(),
+            json!({
+                "simple_key": "simple_value",
+                "key_0": "value1",
+                "key_1_key": "value2",
+                "key_2_nested_array_0": "nested1",
+                "key_2_nested_array_1": "nested2",
+                "key_2_nested_array_2_0": "nested3",
+                "key_2_nested_array_2_1": "nested4"
+            })
+        );
+
+        let opts = FlattenOptions::new().with_array_mode(ArrayMode::Keep);
+        assert_eq!(flatten_with(obj.clone(), &opts).unwrap(), obj);
+
+        let opts = FlattenOptions::new().with_array_mode(ArrayMode::Explode);
+        assert_eq!(
+            flatten_records(obj, &opts).unwrap(),
+            vec![
+                json!({"simple_key": "simple_value", "key": "value1"}),
+                json!({"simple_key": "simple_value", "key_key": "value2"}),
+                json!({"simple_key": "simple_value", "key_nested_array": "nested1"}),
+                json!({"simple_key": "simple_value", "key_nested_array": "nested2"}),
+                json!({"simple_key": "simple_value", "key_nested_array": "nested3"}),
+                json!({"simple_key": "simple_value", "key_nested_array": "nested4"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn explode_several_arrays() {
+        let obj = json!({"host": "a", "tags": ["x", "y"], "ports": [80, 443], "empty": []});
+        let opts = FlattenOptions::new().with_array_mode(ArrayMode::Explode);
+        let records = flatten_records(obj, &opts).unwrap();
+        assert_eq!(records.len(), 4);
+        assert!(records.contains(&json!({"host": "a", "tags": "x", "ports": 80})));
+        assert!(records.contains(&json!({"host": "a", "tags": "y", "ports": 443})));
+
+        // the other modes give a single record
+        let obj = json!({"tags": ["x", "y"]});
+        assert_eq!(
+            flatten_records(obj, &FlattenOptions::default()).unwrap(),
+            vec![json!({"tags": "[\"x\",\"y\"]"})]
+        );
+    }
+
+    #[test]
+    fn collision_error() {
+        let obj = json!({"a_b": 1, "a": {"B": 2}});
+        let err = flatten(obj).unwrap_err();
+        let collision = err.downcast_ref::<KeyCollision>().unwrap();
+        assert_eq!(collision.key, "a_b");
+        assert_eq!(collision.paths, vec!["a.B", "a_b"]);
+
+        // formatting the keys of a flat object
+        let err = flatten(json!({"Level": "info", "level": "warn"})).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "key level collides after flattening: Level, level"
+        );
+
+        let obj = json!({"tags": ["a", {"x": 1}], "tags_1": {"x": 2}});
+        let opts = FlattenOptions::new().with_array_mode(ArrayMode::Expand);
+        let err = flatten_with(obj, &opts).unwrap_err();
+        let collision = err.downcast_ref::<KeyCollision>().unwrap();
+        assert_eq!(collision.key, "tags_1_x");
+        assert_eq!(collision.paths, vec!["tags[1].x", "tags_1.x"]);
+    }
+
+    #[test]
+    fn collision_strategies() {
+        // the keys of an object are flattened in order
+        let obj = json!({"a_b": 1, "a": {"b": 2, "c": 3}, "a_c": 4});
+        for (on_collision, expected) in [
+            (OnCollision::KeepFirst, json!({"a_b": 2, "a_c": 3})),
+            (OnCollision::KeepLast, json!({"a_b": 1, "a_c": 4})),
+            (
+                OnCollision::Suffix,
+                json!({"a_b": 2, "a_b_1": 1, "a_c": 3, "a_c_1": 4}),
+            ),
+        ] {
+            let opts = FlattenOptions::new().with_on_collision(on_collision);
+            let (flat, collisions) = flatten_with_collisions(obj.clone(), &opts).unwrap();
+            assert_eq!(flat, expected, "{on_collision:?}");
+            assert_eq!(
+                collisions,
+                vec![
+                    KeyCollision {
+                        key: "a_b".to_string(),
+                        paths: vec!["a.b".to_string(), "a_b".to_string()],
+                    },
+                    KeyCollision {
+                        key: "a_c".to_string(),
+                        paths: vec!["a.c".to_string(), "a_c".to_string()],
+                    },
+                ],
+                "{on_collision:?}"
+            );
+        }
+
+        // suffixes skip keys which are taken
+        let obj = json!({"k": 1, "K": 2, "k_1": 3});
+        let opts = FlattenOptions::new().with_on_collision(OnCollision::Suffix);
+        assert_eq!(
+            flatten_with(obj, &opts).unwrap(),
+            json!({"k": 2, "k_1": 1, "k_1_1": 3})
+        );
+    }
+
+    #[test]
+    fn suffix_with_separator_and_max_key_len() {
+        let obj = json!({"a": {"b": 1}, "A": {"B": 2}});
+        let opts = FlattenOptions::new()
+            .with_separator(".")
+            .with_on_collision(OnCollision::Suffix);
+        assert_eq!(
+            flatten_with(obj.clone(), &opts).unwrap(),
+            json!({"a.b": 2, "a.b.1": 1})
+        );
+
+        // the suffixed key is cut, not the suffix, the keys sort as ABCD,
+        // ABCd, abcd
+        let obj = json!({"abcd": 1, "ABCD": 2, "ABCd": 3});
+        let opts = FlattenOptions::new()
+            .with_on_collision(OnCollision::Suffix)
+            .with_max_key_len(4);
+        let err = flatten_with(obj.clone(), &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "key abcd_1 of ABCd is longer than 4 characters"
+        );
+        let opts = opts.with_on_limit(OnLimit::Truncate);
+        assert_eq!(
+            flatten_with(obj.clone(), &opts).unwrap(),
+            json!({"abcd": 2, "ab_1": 3, "ab_2": 1})
+        );
+
+        // no room for a suffix
+        let opts = opts.with_max_key_len(1);
+        assert_eq!(flatten_with(obj, &opts).unwrap(), json!({"a": 2}));
+    }
+
+    #[test]
+    fn collision_in_exploded_records() {
+        let obj = json!({"a": [{"b": 1}, 2], "a_b": 3});
+        let opts = FlattenOptions::new().with_array_mode(ArrayMode::Explode);
+        let err = flatten_records(obj.clone(), &opts).unwrap_err();
+        assert!(err.to_string().contains("a.b, a_b"));
+
+        let opts = opts.with_on_collision(OnCollision::KeepLast);
+        assert_eq!(
+            flatten_records(obj, &opts).unwrap(),
+            vec![json!({"a_b": 3}), json!({"a": 2, "a_b": 3})]
+        );
+    }
+
+    /// Builds `{"a": {"a": ... {"a": 1}}}` nested `depth` times.
+    fn deep_object(depth: usize) -> Value {
+        let mut v = json!(1);
+        for _ in 0..depth {
+            // `json!` would serialize `v` again, recursively
+            v = Value::Object(Map::from_iter([("a".to_string(), v)]));
+        }
+        v
+    }
+
+    #[test]
+    fn max_depth() {
+        let obj = json!({"a": {"b": {"c": {"d": 1}}, "e": [1, {"f": 2}], "g": {}}});
+        let opts = FlattenOptions::new().with_max_depth(2);
+        assert_eq!(
+            flatten_with(obj.clone(), &opts).unwrap(),
+            json!({"a_b": "{\"c\":{\"d\":1}}", "a_e": "[1,{\"f\":2}]"})
+        );
+
+        let opts = opts.with_array_mode(ArrayMode::Expand);
+        assert_eq!(
+            flatten_with(obj.clone(), &opts).unwrap(),
+            json!({"a_b": "{\"c\":{\"d\":1}}", "a_e": "[1,{\"f\":2}]"})
+        );
+        let opts = opts.with_max_depth(3);
+        assert_eq!(
+            flatten_with(obj, &opts).unwrap(),
+            json!({"a_b_c": "{\"d\":1}", "a_e_0": 1, "a_e_1": "{\"f\":2}"})
+        );
+
+        let opts = FlattenOptions::new()
+            .with_max_depth(2)
+            .with_array_mode(ArrayMode::Explode);
+        let obj = json!({"a": [[1, [2]], 3], "b": {"c": 1}});
+        assert_eq!(
+            flatten_records(obj.clone(), &opts).unwrap(),
+            vec![json!({"a": "[1,[2]]", "b_c": 1}), json!({"a": 3, "b_c": 1})]
+        );
+        assert_eq!(
+            flatten_records(obj, &opts.with_max_depth(1)).unwrap(),
+            vec![json!({"a": "[[1,[2]],3]", "b": "{\"c\":1}"})]
+        );
+    }
+
+    #[test]
+    fn deep_payload() {
+        let obj = deep_object(500);
+        let opts = FlattenOptions::new().with_max_depth(4);
+        let flat = flatten_with(obj, &opts).unwrap();
+        let flat = flat.as_object().unwrap();
+        assert_eq!(flat.len(), 1);
+        let rest = flat["a_a_a_a"].as_str().unwrap();
+        assert_eq!(rest.matches("{\"a\":").count(), 496);
+
+        let opts = opts.with_array_mode(ArrayMode::Explode);
+        assert_eq!(flatten_records(deep_object(500), &opts).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn deep_in_memory() {
+        // far deeper than serde_json parses, which would overflow the stack
+        // when flattened, stringified or dropped recursively
+        let depth = 100_000;
+        let flat = flatten(deep_object(depth)).unwrap();
+        let flat = flat.as_object().unwrap();
+        assert_eq!(flat.len(), 1);
+        let (key, rest) = flat.iter().next().unwrap();
+        assert_eq!(key.split('_').count(), DEFAULT_MAX_DEPTH as usize);
+        let rest = rest.as_str().unwrap();
+        assert_eq!(
+            rest.matches("{\"a\":").count(),
+            depth - DEFAULT_MAX_DEPTH as usize
+        );
+
+        let mut arr = json!(1);
+        for _ in 0..depth {
+            arr = Value::Array(vec![arr]);
+        }
+        let obj = Value::Object(Map::from_iter([("a".to_string(), arr)]));
+        let flat = flatten(obj).unwrap();
+        assert_eq!(flat["a"].as_str().unwrap().len(), 2 * depth + 1);
+
+        let opts = FlattenOptions::new().with_exclude(Pattern::glob("a"));
+        let mut obj = json!({"b": 1});
+        obj["a"] = deep_object(depth);
+        assert_eq!(flatten_with(obj, &opts).unwrap(), json!({"b": 1}));
+    }
+
+    #[test]
+    fn json_string() {
+        let v = json!({"a": [1, -2, 2.5, "x\"y\n", null, true, [], {}], "b\u{1}": {"c": [[]]}});
+        assert_eq!(into_json_string(v.clone()), v.to_string());
+        assert_eq!(into_json_string(json!("é")), "\"é\"");
+    }
+
+    #[test]
+    fn max_fields() {
+        let wide = Value::Object((0..100_000).map(|i| (format!("f{i}"), json!(i))).collect());
+        let nested = json!({"a": wide.clone()});
+
+        let opts = FlattenOptions::new().with_max_fields(1000);
+        for obj in [wide.clone(), nested.clone()] {
+            let err = flatten_with(obj, &opts).unwrap_err();
+            assert!(err.to_string().contains("more than 1000 fields"), "{err}");
+        }
+
+        let opts = opts.with_on_limit(OnLimit::Truncate);
+        for obj in [wide, nested] {
+            let flat = flatten_with(obj, &opts).unwrap();
+            assert_eq!(flat.as_object().unwrap().len(), 1000);
+        }
+
+        // fields within the limit take the fast path
+        let obj = json!({"a": 1, "b": 2});
+        let opts = FlattenOptions::new().with_max_fields(2);
+        assert_eq!(flatten_with(obj.clone(), &opts).unwrap(), obj);
+    }
+
+    #[test]
+    fn max_key_len() {
+        let long = "k".repeat(10_000);
+        let obj = json!({ long.clone(): 1, "a": { long.clone(): 2 }, "short": 3 });
+
+        let opts = FlattenOptions::new().with_max_key_len(16);
+        let err = flatten_with(obj.clone(), &opts).unwrap_err();
+        assert!(
+            err.to_string().contains("longer than 16 characters"),
+            "{err}"
+        );
+        let err = flatten_with(json!({ long.clone(): 1 }), &opts).unwrap_err();
+        assert!(
+            err.to_string().starts_with("key kkkkkkkkkkkkkkkk of"),
+            "{err}"
+        );
+
+        // keys cut to the same prefix collide
+        let opts = opts
+            .with_on_limit(OnLimit::Truncate)
+            .with_on_collision(OnCollision::Suffix);
+        assert_eq!(
+            flatten_with(obj, &opts).unwrap(),
+            json!({"a_kkkkkkkkkkkkkk": 2, "kkkkkkkkkkkkkkkk": 1, "short": 3})
+        );
+
+        // characters, not bytes
+        let opts = FlattenOptions::new().with_max_key_len(4);
+        let obj = json!({"größe": 1});
+        assert!(flatten_with(obj.clone(), &opts).is_err());
+        let opts = opts.with_on_limit(OnLimit::Truncate);
+        assert_eq!(flatten_with(obj, &opts).unwrap(), json!({"größ": 1}));
+        let opts = FlattenOptions::new().with_max_key_len(5);
+        assert_eq!(
+            flatten_with(json!({"größe": 1}), &opts).unwrap(),
+            json!({"größe": 1})
+        );
+    }
+
+    #[test]
+    fn field_rules() {
+        let obj = json!({
+            "kubernetes": {
+                "annotations": {"checksum/config": "ab12", "prometheus.io/scrape": "true"},
+                "pod": {"name": "web-7d9f", "uid": "a1b2"},
+            },
+            "log": "GET /",
+            "http": {"status": 200, "tags": ["a"]},
+        });
+        let opts = FlattenOptions::new()
+            .with_exclude(Pattern::glob("kubernetes_annotations_*"))
+            .with_exclude(Pattern::glob("*_uid"))
+            .with_rename("log", "message")
+            .with_rename("kubernetes_pod", "pod");
+        assert_eq!(
+            flatten_with(obj.clone(), &opts).unwrap(),
+            json!({
+                "pod_name": "web-7d9f",
+                "message": "GET /",
+                "http_status": 200,
+                "http_tags": "[\"a\"]",
+            })
+        );
+
+        // included objects keep all their fields, arrays are matched whole
+        let opts = FlattenOptions::new()
+            .with_include(Pattern::glob("kubernetes_pod"))
+            .with_include(Pattern::regex("^http_").unwrap())
+            .with_exclude(Pattern::glob("http_tags"));
+        assert_eq!(
+            flatten_with(obj.clone(), &opts).unwrap(),
+            json!({"kubernetes_pod_name": "web-7d9f", "kubernetes_pod_uid": "a1b2", "http_status": 200})
+        );
+
+        // excluded objects aren't visited, so they don't count to the limits
+        let opts = FlattenOptions::new()
+            .with_exclude(Pattern::glob("kubernetes"))
+            .with_exclude(Pattern::glob("http"))
+            .with_max_fields(1);
+        assert_eq!(flatten_with(obj, &opts).unwrap(), json!({"log": "GET /"}));
+
+        // renamed keys collide like any other
+        let opts = FlattenOptions::new().with_rename("a", "b");
+        assert!(flatten_with(json!({"a": 1, "b": 2}), &opts).is_err());
+    }
+
+    #[test]
+    fn explode_max_records() {
+        let arr = Value::Array((0..1000).map(Value::from).collect());
+        let obj = json!({"a": arr.clone(), "b": arr.clone(), "c": arr});
+        let opts = FlattenOptions::new()
+            .with_array_mode(ArrayMode::Explode)
+            .with_max_records(10_000);
+        let err = flatten_records(obj.clone(), &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "more than 10000 records after exploding arrays"
+        );
+
+        let opts = opts.with_on_limit(OnLimit::Truncate);
+        let records = flatten_records(obj, &opts).unwrap();
+        assert_eq!(records.len(), 10_000);
+        assert_eq!(records[0], json!({"a": 0, "b": 0, "c": 0}));
+        assert_eq!(records[9_999], json!({"a": 0, "b": 9, "c": 999}));
+
+        // nested arrays add up
+        let obj = json!({"a": [[1, 2], [3, 4]]});
+        let opts = FlattenOptions::new()
+            .with_array_mode(ArrayMode::Explode)
+            .with_max_records(3);
+        assert!(flatten_records(obj.clone(), &opts).is_err());
+        let opts = opts.with_on_limit(OnLimit::Truncate);
+        assert_eq!(
+            flatten_records(obj, &opts).unwrap(),
+            vec![json!({"a": 1}), json!({"a": 2}), json!({"a": 3})]
+        );
+    }
+
+    #[test]
+    fn explode_with_rules() {
+        let obj = json!({"host": "a", "tags": ["x", "y"], "spans": [{"id": 1}, {"id": 2}]});
+        let opts = FlattenOptions::new()
+            .with_array_mode(ArrayMode::Explode)
+            .with_exclude(Pattern::glob("tags"))
+            .with_rename("spans", "span");
+        assert_eq!(
+            flatten_records(obj, &opts).unwrap(),
+            vec![
+                json!({"host": "a", "span_id": 1}),
+                json!({"host": "a", "span_id": 2}),
+            ]
+        );
+    }
 }
@@ -1,30 +1,86 @@
+use bytes::Bytes;
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 use pprof::criterion::{Output, PProfProfiler};
 use std::time::Duration;
 
-use memory_cache::memory_v1 as memory;
+use memory_cache::{memory_v1, CacheConfig, FileCache, Policy};
+
+const MAX_SIZE: usize = 64 * 1024 * 1024;
+const RELEASE_SIZE: usize = 8 * 1024 * 1024;
+
+fn bench_backend<C: FileCache>(
+    group: &mut criterion::BenchmarkGroup<'_, criterion::measurement::WallTime>,
+    rt: &tokio::runtime::Runtime,
+    alias: &str,
+    cache: C,
+) {
+    let session_id = "test";
+    let data = Bytes::from("DATA.DATA.".repeat(10240));
+    group.bench_function(BenchmarkId::from_parameter(format!("{alias}-set")), |b| {
+        let mut i = 0u64;
+        b.to_async(rt).iter(|| {
+            i += 1;
+            let file = format!("files/{i}.parquet");
+            let (cache, data) = (&cache, data.clone());
+            async move {
+                let _ = cache
+                    .set(black_box(session_id), black_box(&file), data)
+                    .await;
+            }
+        })
+    });
+    rt.block_on(async {
+        for i in 0..100 {
+            let _ = cache
+                .set(session_id, &format!("hot/{i}.parquet"), data.clone())
+                .await;
+        }
+    });
+    group.bench_function(BenchmarkId::from_parameter(format!("{alias}-get")), |b| {
+        let mut i = 0u64;
+        b.to_async(rt).iter(|| {
+            i += 1;
+            let file = format!("hot/{}.parquet", i % 100);
+            let cache = &cache;
+            async move {
+                let _ = cache.get(black_box(&file)).await;
+            }
+        })
+    });
+    group.bench_function(
+        BenchmarkId::from_parameter(format!("{alias}-get_range")),
+        |b| {
+            let mut i = 0u64;
+            b.to_async(rt).iter(|| {
+                i += 1;
+                let file = format!("hot/{}.parquet", i % 100);
+                let cache = &cache;
+                async move {
+                    let _ = cache.get_range(black_box(&file), 1024..4096).await;
+                }
+            })
+        },
+    );
+}
 
 pub fn ben_benchmark(c: &mut Criterion) {
     let rt = tokio::runtime::Runtime::new().unwrap();
     let mut group: criterion::BenchmarkGroup<'_, criterion::measurement::WallTime> =
         c.benchmark_group("memory");
     group.measurement_time(Duration::from_secs(8));
-    for alias in ["v1", "v2", "v3"] {
-        let h = match alias {
-            "v1" => memory::download,
-            "v2" => memory::download,
-            "v3" => memory::download,
-            _ => panic!("not support version"),
-        };
-        group.bench_function(
-            BenchmarkId::from_parameter(format!("{alias}-download")),
-            |b| {
-                b.to_async(&rt).iter(|| async {
-                    let session_id = "test";
-                    let file = "test.parquet";
-                    let _ = h(black_box(session_id), black_box(file)).await;
-                })
-            },
+    // a single backend, runs differ in the eviction policy only
+    for (alias, policy) in [
+        ("lru", Policy::Lru),
+        ("fifo", Policy::Fifo),
+        ("lfu", Policy::Lfu),
+        ("s3fifo", Policy::S3Fifo),
+    ] {
+        let cfg = CacheConfig::with_capacity(alias, MAX_SIZE, RELEASE_SIZE).with_policy(policy);
+        bench_backend(
+            &mut group,
+            &rt,
+            alias,
+            memory_v1::FileCache::with_config(cfg),
         );
     }
 }
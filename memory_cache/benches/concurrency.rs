@@ -0,0 +1,98 @@
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pprof::criterion::{Output, PProfProfiler};
+use std::time::Duration;
+
+use memory_cache::{memory_v1, CacheConfig, FileCache, ShardedCache};
+
+const MAX_SIZE: usize = 128 * 1024 * 1024;
+const RELEASE_SIZE: usize = 8 * 1024 * 1024;
+const FILES: usize = 10_000;
+const OPS_PER_TASK: usize = 1_000;
+const SHARDS: usize = 16;
+
+/// Every task runs a mix of one `set` for nine `get`/`exist` calls, keys are
+/// spread over a working set a bit larger than the cache so writes keep
+/// evicting.
+async fn run_tasks<C: FileCache + Clone>(cache: &C, tasks: usize, data: &Bytes) {
+    let mut handles = Vec::with_capacity(tasks);
+    for t in 0..tasks {
+        let (cache, data) = (cache.clone(), data.clone());
+        handles.push(tokio::spawn(async move {
+            for i in 0..OPS_PER_TASK {
+                let file = format!("files/{}.parquet", (t * 7919 + i * 31) % (FILES * 2));
+                match i % 10 {
+                    0 => {
+                        let _ = cache.set("bench", &file, data.clone()).await;
+                    }
+                    1..=4 => {
+                        let _ = cache.exist(&file).await;
+                    }
+                    _ => {
+                        let _ = cache.get(&file).await;
+                    }
+                }
+            }
+        }));
+    }
+    for handle in handles {
+        handle.await.unwrap();
+    }
+}
+
+fn bench_backend<C: FileCache + Clone>(
+    group: &mut criterion::BenchmarkGroup<'_, criterion::measurement::WallTime>,
+    rt: &tokio::runtime::Runtime,
+    alias: &str,
+    tasks: usize,
+    cache: C,
+) {
+    let data = Bytes::from("DATA.DATA.".repeat(1024));
+    rt.block_on(async {
+        for i in 0..FILES {
+            let _ = cache
+                .set("bench", &format!("files/{i}.parquet"), data.clone())
+                .await;
+        }
+    });
+    group.bench_function(BenchmarkId::new(alias, tasks), |b| {
+        b.to_async(rt).iter(|| run_tasks(&cache, tasks, &data))
+    });
+}
+
+pub fn concurrency_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrency");
+    group.measurement_time(Duration::from_secs(8));
+    for threads in [1, 2, 4, 8] {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(threads)
+            .enable_all()
+            .build()
+            .unwrap();
+        group.throughput(Throughput::Elements((threads * OPS_PER_TASK) as u64));
+        let cfg = CacheConfig::with_capacity("bench", MAX_SIZE, RELEASE_SIZE);
+        bench_backend(
+            &mut group,
+            &rt,
+            "v1",
+            threads,
+            memory_v1::FileCache::with_config(cfg.clone()),
+        );
+        bench_backend(
+            &mut group,
+            &rt,
+            "v1-sharded",
+            threads,
+            ShardedCache::with_config(cfg, SHARDS, memory_v1::FileCache::with_config),
+        );
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)));
+    targets = concurrency_benchmark
+}
+
+criterion_main!(benches);
@@ -0,0 +1,107 @@
+//! Replays synthetic access traces against every eviction policy and prints
+//! the hit ratio of each one.
+//!
+//! ```
+//! cargo bench -p memory_cache --bench hit_ratio
+//! ```
+
+use memory_cache::{EvictionPolicy, Policy};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const POLICIES: [Policy; 4] = [Policy::Lru, Policy::Fifo, Policy::Lfu, Policy::S3Fifo];
+const KEYS: usize = 10_000;
+const REQUESTS: usize = 200_000;
+const CAPACITY: usize = 1_000;
+
+struct Zipf {
+    cdf: Vec<f64>,
+}
+
+impl Zipf {
+    fn new(n: usize, s: f64) -> Zipf {
+        let mut cdf = Vec::with_capacity(n);
+        let mut sum = 0.0;
+        for k in 1..=n {
+            sum += 1.0 / (k as f64).powf(s);
+            cdf.push(sum);
+        }
+        cdf.iter_mut().for_each(|v| *v /= sum);
+        Zipf { cdf }
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> usize {
+        let p: f64 = rng.gen();
+        self.cdf.partition_point(|v| *v < p)
+    }
+}
+
+/// Skewed popularity, the typical working set of a query node.
+fn zipf_trace(rng: &mut StdRng) -> Vec<String> {
+    let zipf = Zipf::new(KEYS, 1.0);
+    (0..REQUESTS)
+        .map(|_| format!("files/{}.parquet", zipf.sample(rng)))
+        .collect()
+}
+
+/// Skewed popularity interrupted by large one-off scans.
+fn zipf_scan_trace(rng: &mut StdRng) -> Vec<String> {
+    let zipf = Zipf::new(KEYS, 1.0);
+    let mut trace = Vec::with_capacity(REQUESTS);
+    let mut scan = 0;
+    while trace.len() < REQUESTS {
+        for _ in 0..20_000 {
+            trace.push(format!("files/{}.parquet", zipf.sample(rng)));
+        }
+        for _ in 0..5_000 {
+            scan += 1;
+            trace.push(format!("scan/{scan}.parquet"));
+        }
+    }
+    trace
+}
+
+/// A loop slightly larger than the cache, the worst case of LRU.
+fn loop_trace(_rng: &mut StdRng) -> Vec<String> {
+    (0..REQUESTS)
+        .map(|i| format!("files/{}.parquet", i % (CAPACITY * 6 / 5)))
+        .collect()
+}
+
+fn replay(policy: &mut dyn EvictionPolicy, trace: &[String]) -> f64 {
+    let mut hits = 0;
+    for key in trace {
+        if policy.contains(key) {
+            hits += 1;
+            policy.access(key);
+            continue;
+        }
+        policy.insert(key, 1);
+        while policy.len() > CAPACITY {
+            policy.evict();
+        }
+    }
+    hits as f64 / trace.len() as f64
+}
+
+fn main() {
+    type Trace = fn(&mut StdRng) -> Vec<String>;
+    let traces: [(&str, Trace); 3] = [
+        ("zipf", zipf_trace),
+        ("zipf+scan", zipf_scan_trace),
+        ("loop", loop_trace),
+    ];
+    print!("{:<12}", "trace");
+    for policy in POLICIES {
+        print!("{:>10}", format!("{policy:?}"));
+    }
+    println!();
+    for (name, trace) in traces {
+        let trace = trace(&mut StdRng::seed_from_u64(42));
+        print!("{name:<12}");
+        for policy in POLICIES {
+            let ratio = replay(policy.build().as_mut(), &trace);
+            print!("{:>9.2}%", ratio * 100.0);
+        }
+        println!();
+    }
+}
@@ -0,0 +1,314 @@
+//! Cache of decoded Arrow IPC streams.
+//!
+//! [`FileCache`] backends keep the raw IPC bytes, so every read pays for
+//! decoding them again. A [`BatchCache`] keeps the decoded record batches
+//! instead, accounted with their real in-memory size.
+
+use arrow::{datatypes::SchemaRef, ipc::reader::StreamReader, record_batch::RecordBatch};
+use bytes::Bytes;
+use hashbrown::HashMap;
+use snafu::{ensure, ResultExt};
+use std::{io::Cursor, ops::Range, sync::Arc};
+use tokio::sync::RwLock;
+
+use crate::{
+    config::CacheConfig,
+    errors::{DecodeSnafu, EntryTooLargeSnafu},
+    eviction::{EvictionPolicy, Policy},
+    stats::{CacheStats, Metrics},
+    FileCache, Result,
+};
+
+const DEFAULT_POLICY: Policy = Policy::Lru;
+
+/// Decoded record batches of an IPC stream, cheap to clone as the column
+/// buffers are shared.
+#[derive(Clone, Debug)]
+pub struct Batches {
+    pub schema: SchemaRef,
+    pub batches: Vec<RecordBatch>,
+}
+
+impl Batches {
+    pub fn num_rows(&self) -> usize {
+        self.batches.iter().map(|b| b.num_rows()).sum()
+    }
+
+    /// Memory held by the column buffers.
+    pub fn memory_size(&self) -> usize {
+        self.batches.iter().map(|b| b.get_array_memory_size()).sum()
+    }
+
+    /// Keeps the columns at `projection`, in that order.
+    pub fn project(&self, projection: &[usize]) -> Option<Batches> {
+        let schema = Arc::new(self.schema.project(projection).ok()?);
+        let batches = self
+            .batches
+            .iter()
+            .map(|b| b.project(projection))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .ok()?;
+        Some(Batches { schema, batches })
+    }
+
+    /// Keeps the rows in `rows`, counted across all batches. Returns `None`
+    /// if `rows` is out of bounds.
+    pub fn slice(&self, rows: Range<usize>) -> Option<Batches> {
+        if rows.start > rows.end || rows.end > self.num_rows() {
+            return None;
+        }
+        let mut batches = Vec::new();
+        let mut offset = 0;
+        for batch in self.batches.iter() {
+            let start = rows.start.max(offset);
+            let end = rows.end.min(offset + batch.num_rows());
+            if start < end {
+                batches.push(batch.slice(start - offset, end - start));
+            }
+            offset += batch.num_rows();
+        }
+        Some(Batches {
+            schema: self.schema.clone(),
+            batches,
+        })
+    }
+}
+
+/// A cheaply cloneable handle to a cache of decoded Arrow IPC streams.
+///
+/// Entries are keyed by file and column projection. A projection which is
+/// not cached on its own is served from the whole file if that one is.
+#[derive(Clone)]
+pub struct BatchCache {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    name: String,
+    files: RwLock<BatchData>,
+    metrics: Metrics,
+}
+
+struct BatchData {
+    max_size: usize,
+    cur_size: usize,
+    index: Box<dyn EvictionPolicy>,
+    data: HashMap<String, Batches>,
+}
+
+impl BatchCache {
+    pub fn new(name: &str, max_size: usize) -> BatchCache {
+        BatchCache::with_config(CacheConfig::with_capacity(name, max_size, 0))
+    }
+
+    /// Only the name, `max_size` and the policy of `cfg` apply, decoded
+    /// batches never expire nor spill to disk.
+    pub fn with_config(cfg: CacheConfig) -> BatchCache {
+        BatchCache {
+            inner: Arc::new(Inner {
+                files: RwLock::new(BatchData {
+                    max_size: cfg.max_size,
+                    cur_size: 0,
+                    index: cfg.policy.unwrap_or(DEFAULT_POLICY).build(),
+                    data: HashMap::new(),
+                }),
+                metrics: Metrics::default(),
+                name: cfg.name,
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// Returns the cached batches of `file`, limited to the columns of
+    /// `projection` and the rows in `rows` when given.
+    pub async fn get(
+        &self,
+        file: &str,
+        projection: Option<&[usize]>,
+        rows: Option<Range<usize>>,
+    ) -> Option<Batches> {
+        let is_range = rows.is_some();
+        let batches = self.lookup(file, projection).await;
+        let batches = match rows {
+            Some(rows) => batches.and_then(|b| b.slice(rows)),
+            None => batches,
+        };
+        self.inner.metrics.record_get(batches.is_some(), is_range);
+        batches
+    }
+
+    async fn lookup(&self, file: &str, projection: Option<&[usize]>) -> Option<Batches> {
+        let mut files = self.inner.files.write().await;
+        let exact = key(file, projection);
+        if let Some(batches) = files.data.get(&exact).cloned() {
+            files.index.access(&exact);
+            return Some(batches);
+        }
+        let projection = projection?;
+        let whole = key(file, None);
+        let batches = files.data.get(&whole)?.project(projection)?;
+        files.index.access(&whole);
+        Some(batches)
+    }
+
+    /// Decodes the IPC stream `data` of `file`, keeping only the columns of
+    /// `projection`, and caches the batches. Returns the decoded batches.
+    pub async fn set(
+        &self,
+        file: &str,
+        data: Bytes,
+        projection: Option<&[usize]>,
+    ) -> Result<Batches> {
+        let batches = decode(file, data, projection)?;
+        self.insert(file, projection, batches.clone()).await?;
+        Ok(batches)
+    }
+
+    /// Returns the cached batches of `file`, or decodes the IPC bytes held
+    /// by `source` on a miss. Returns `None` if neither cache has the file.
+    pub async fn get_or_decode<C: FileCache + ?Sized>(
+        &self,
+        source: &C,
+        file: &str,
+        projection: Option<&[usize]>,
+        rows: Option<Range<usize>>,
+    ) -> Result<Option<Batches>> {
+        if let Some(batches) = self.get(file, projection, rows.clone()).await {
+            return Ok(Some(batches));
+        }
+        let Some(data) = source.get(file).await else {
+            return Ok(None);
+        };
+        let batches = self.set(file, data, projection).await?;
+        Ok(match rows {
+            Some(rows) => batches.slice(rows),
+            None => Some(batches),
+        })
+    }
+
+    async fn insert(
+        &self,
+        file: &str,
+        projection: Option<&[usize]>,
+        batches: Batches,
+    ) -> Result<()> {
+        let key = key(file, projection);
+        let data_size = key.len() + batches.memory_size();
+        let mut files = self.inner.files.write().await;
+        ensure!(
+            data_size <= files.max_size,
+            EntryTooLargeSnafu {
+                name: &self.inner.name,
+                file,
+                size: data_size,
+                max_size: files.max_size,
+            }
+        );
+        if files.index.contains(&key) {
+            return Ok(());
+        }
+        let (mut evictions, mut evicted_bytes) = (0, 0);
+        while files.cur_size + data_size > files.max_size {
+            let Some((victim, size)) = files.index.evict() else {
+                break;
+            };
+            files.data.remove(&victim);
+            files.cur_size -= size;
+            evictions += 1;
+            evicted_bytes += size;
+        }
+        files.cur_size += data_size;
+        files.index.insert(&key, data_size);
+        files.data.insert(key, batches);
+        drop(files);
+        self.inner.metrics.record_insert();
+        self.inner
+            .metrics
+            .record_evictions(evictions, evicted_bytes);
+        Ok(())
+    }
+
+    /// Drops every cached projection of `file`, returns how many were
+    /// removed.
+    pub async fn remove(&self, file: &str) -> usize {
+        let mut files = self.inner.files.write().await;
+        let whole = key(file, None);
+        let prefix = format!("{whole}{PROJECTION_SEP}");
+        let keys = files
+            .data
+            .keys()
+            .filter(|key| **key == whole || key.starts_with(&prefix))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in keys.iter() {
+            files.data.remove(key);
+            if let Some(size) = files.index.remove(key) {
+                files.cur_size -= size;
+            }
+        }
+        keys.len()
+    }
+
+    pub async fn len(&self) -> usize {
+        self.inner.files.read().await.data.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    pub async fn stats(&self) -> CacheStats {
+        let mut stats = {
+            let files = self.inner.files.read().await;
+            CacheStats {
+                name: self.inner.name.clone(),
+                entries: files.data.len(),
+                cur_size: files.cur_size,
+                max_size: files.max_size,
+                ..Default::default()
+            }
+        };
+        self.inner.metrics.fill(&mut stats);
+        stats
+    }
+}
+
+/// Separates the file from the projected columns in cache keys, it can't
+/// appear in a file name.
+const PROJECTION_SEP: char = '\0';
+
+fn key(file: &str, projection: Option<&[usize]>) -> String {
+    match projection {
+        None => file.to_string(),
+        Some(projection) => {
+            let columns = projection
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            format!("{file}{PROJECTION_SEP}{columns}")
+        }
+    }
+}
+
+fn decode(file: &str, data: Bytes, projection: Option<&[usize]>) -> Result<Batches> {
+    let reader = StreamReader::try_new(Cursor::new(data), projection.map(|p| p.to_vec()))
+        .context(DecodeSnafu { file })?;
+    // the reader reports the schema of the whole stream
+    let schema = match projection {
+        Some(projection) => Arc::new(
+            reader
+                .schema()
+                .project(projection)
+                .context(DecodeSnafu { file })?,
+        ),
+        None => reader.schema(),
+    };
+    let batches = reader
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .context(DecodeSnafu { file })?;
+    Ok(Batches { schema, batches })
+}
@@ -0,0 +1,100 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::eviction::Policy;
+
+pub const DEFAULT_MAX_SIZE: usize = 1024 * 1024 * 1024;
+pub const DEFAULT_RELEASE_SIZE: usize = 1024 * 1024 * 100;
+pub const DEFAULT_PROMOTE_HITS: usize = 2;
+
+/// Settings of a single cache instance.
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+    /// Name of the cache, used in log lines to tell instances apart.
+    pub name: String,
+    /// Maximum bytes (keys + values) kept in memory.
+    pub max_size: usize,
+    /// Minimum bytes released at once when the cache is full.
+    pub release_size: usize,
+    /// Eviction policy, `None` picks the default of the backend.
+    pub policy: Option<Policy>,
+    /// Optional disk tier receiving entries evicted from memory.
+    pub disk: Option<DiskConfig>,
+    /// TTL applied by `set`, entries never expire when `None`.
+    pub default_ttl: Option<Duration>,
+}
+
+/// Settings of the disk tier of a cache.
+#[derive(Clone, Debug)]
+pub struct DiskConfig {
+    /// Directory holding the spilled files in a `memory_cache-<name>`
+    /// subdirectory, caches sharing it need different names.
+    pub dir: PathBuf,
+    /// Maximum bytes (keys + values) kept on disk.
+    pub max_size: usize,
+    /// Number of disk hits after which a file is moved back into memory,
+    /// `0` disables promotion.
+    pub promote_hits: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self::new("default")
+    }
+}
+
+impl CacheConfig {
+    pub fn new(name: &str) -> CacheConfig {
+        CacheConfig {
+            name: name.to_string(),
+            max_size: DEFAULT_MAX_SIZE,
+            release_size: DEFAULT_RELEASE_SIZE,
+            policy: None,
+            disk: None,
+            default_ttl: None,
+        }
+    }
+
+    pub fn with_capacity(name: &str, max_size: usize, release_size: usize) -> CacheConfig {
+        CacheConfig {
+            name: name.to_string(),
+            max_size,
+            release_size,
+            policy: None,
+            disk: None,
+            default_ttl: None,
+        }
+    }
+
+    /// Sizes the cache as a fraction of the cgroup memory limit, or of the
+    /// node memory when there is no limit.
+    pub fn with_memory_fraction(mut self, fraction: f64) -> CacheConfig {
+        self.max_size = (cgroup::get_memory_limit() as f64 * fraction) as usize;
+        self.release_size = self.release_size.min(self.max_size / 10);
+        self
+    }
+
+    pub fn with_policy(mut self, policy: Policy) -> CacheConfig {
+        self.policy = Some(policy);
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> CacheConfig {
+        self.default_ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_disk(mut self, dir: impl Into<PathBuf>, max_size: usize) -> CacheConfig {
+        self.disk = Some(DiskConfig::new(dir, max_size));
+        self
+    }
+}
+
+impl DiskConfig {
+    pub fn new(dir: impl Into<PathBuf>, max_size: usize) -> DiskConfig {
+        DiskConfig {
+            dir: dir.into(),
+            max_size,
+            promote_hits: DEFAULT_PROMOTE_HITS,
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use bytes::Bytes;
+use lru::LruCache;
+use snafu::ResultExt;
+use std::{
+    io::SeekFrom,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    sync::{Mutex, OnceCell},
+    time::Instant,
+};
+
+use crate::{
+    config::DiskConfig,
+    errors::{CacheError, IoSnafu},
+    FileCache,
+};
+
+const FILE_EXTENSION: &str = "cache";
+
+/// Second cache tier that keeps entries evicted from memory on local disk.
+///
+/// Every entry is written to its own file named after a sequence id, so keys
+/// never need to be escaped into valid file names. The files live in a
+/// `memory_cache-<name>` subdirectory of the configured directory, which the
+/// cache owns.
+pub struct DiskCache {
+    dir: PathBuf,
+    /// Set once the directory was emptied of files left behind by a previous
+    /// process and created.
+    ready: OnceCell<()>,
+    max_size: usize,
+    promote_hits: usize,
+    index: Mutex<DiskIndex>,
+    expired: AtomicU64,
+}
+
+struct DiskIndex {
+    next_id: u64,
+    cur_size: usize,
+    data: LruCache<String, DiskEntry>,
+}
+
+#[derive(Clone, Copy)]
+struct DiskEntry {
+    id: u64,
+    size: usize,
+    hits: usize,
+    expires: Option<Instant>,
+}
+
+impl DiskEntry {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+/// An entry released from memory, handed to the disk tier.
+pub(crate) struct Evicted {
+    pub file: String,
+    pub data: Bytes,
+    pub expires: Option<Instant>,
+}
+
+/// Result of a disk tier read.
+pub struct DiskRead {
+    pub data: Bytes,
+    /// The entry is hot enough to move back into memory, `data` then always
+    /// holds the whole file.
+    pub promote: bool,
+    pub expires: Option<Instant>,
+}
+
+impl DiskCache {
+    pub fn new(name: &str, cfg: &DiskConfig) -> DiskCache {
+        DiskCache {
+            dir: cfg.dir.join(format!("memory_cache-{name}")),
+            ready: OnceCell::new(),
+            max_size: cfg.max_size,
+            promote_hits: cfg.promote_hits,
+            index: Mutex::new(DiskIndex {
+                next_id: 0,
+                cur_size: 0,
+                data: LruCache::unbounded(),
+            }),
+            expired: AtomicU64::new(0),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Creates the directory, dropping the files a previous process left in
+    /// it as they are never indexed again.
+    async fn prepare(&self) -> Result<(), CacheError> {
+        match tokio::fs::read_dir(&self.dir).await {
+            Ok(mut entries) => {
+                while let Ok(Some(entry)) = entries.next_entry().await {
+                    let path = entry.path();
+                    if path.extension().is_some_and(|ext| ext == FILE_EXTENSION) {
+                        let _ = tokio::fs::remove_file(path).await;
+                    }
+                }
+                Ok(())
+            }
+            Err(_) => tokio::fs::create_dir_all(&self.dir)
+                .await
+                .context(IoSnafu { path: &self.dir }),
+        }
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{id}.{FILE_EXTENSION}"))
+    }
+
+    pub async fn exist(&self, file: &str) -> bool {
+        let index = self.index.lock().await;
+        index
+            .data
+            .peek(file)
+            .is_some_and(|entry| !entry.is_expired(Instant::now()))
+    }
+
+    /// Writes evicted entries to disk, releasing the least recently used
+    /// disk entries when the disk budget is exceeded.
+    pub async fn put(
+        &self,
+        file: &str,
+        data: Bytes,
+        expires: Option<Instant>,
+    ) -> Result<(), CacheError> {
+        let data_size = file.len() + data.len();
+        if data_size > self.max_size {
+            return Ok(());
+        }
+        let id = {
+            let mut index = self.index.lock().await;
+            index.next_id += 1;
+            index.next_id
+        };
+        self.ready.get_or_try_init(|| self.prepare()).await?;
+        let path = self.path(id);
+        tokio::fs::write(&path, &data)
+            .await
+            .context(IoSnafu { path })?;
+
+        let mut release_files = Vec::new();
+        {
+            let mut index = self.index.lock().await;
+            let entry = DiskEntry {
+                id,
+                size: data_size,
+                hits: 0,
+                expires,
+            };
+            if let Some(old) = index.data.put(file.to_string(), entry) {
+                index.cur_size -= old.size;
+                release_files.push(old.id);
+            }
+            index.cur_size += data_size;
+            while index.cur_size > self.max_size {
+                let Some((_, entry)) = index.data.pop_lru() else {
+                    break;
+                };
+                index.cur_size -= entry.size;
+                release_files.push(entry.id);
+            }
+        }
+        for id in release_files {
+            let _ = tokio::fs::remove_file(self.path(id)).await;
+        }
+        Ok(())
+    }
+
+    /// Reads a file, or only `range` of it, from disk.
+    ///
+    /// Returns `None` if the file is not on disk, the range is out of bounds
+    /// or the read failed.
+    pub async fn get(&self, file: &str, range: Option<Range<usize>>) -> Option<DiskRead> {
+        let (entry, promote) = {
+            let mut index = self.index.lock().await;
+            let entry = index.data.get_mut(file)?;
+            if entry.is_expired(Instant::now()) {
+                drop(index);
+                if self.remove(file).await {
+                    self.expired.fetch_add(1, Ordering::Relaxed);
+                }
+                return None;
+            }
+            entry.hits += 1;
+            (
+                *entry,
+                self.promote_hits > 0 && entry.hits >= self.promote_hits,
+            )
+        };
+        if let Some(range) = &range {
+            if range.start > range.end || range.end > entry.size - file.len() {
+                return None;
+            }
+        }
+        let data = match range {
+            Some(range) if !promote => {
+                let mut f = tokio::fs::File::open(self.path(entry.id)).await.ok()?;
+                f.seek(SeekFrom::Start(range.start as u64)).await.ok()?;
+                let mut buf = vec![0; range.len()];
+                f.read_exact(&mut buf).await.ok()?;
+                buf
+            }
+            _ => tokio::fs::read(self.path(entry.id)).await.ok()?,
+        };
+        Some(DiskRead {
+            data: Bytes::from(data),
+            promote,
+            expires: entry.expires,
+        })
+    }
+
+    /// Drops a file from disk, returns `true` if it was there.
+    pub async fn remove(&self, file: &str) -> bool {
+        let entry = {
+            let mut index = self.index.lock().await;
+            let Some(entry) = index.data.pop(file) else {
+                return false;
+            };
+            index.cur_size -= entry.size;
+            entry
+        };
+        let _ = tokio::fs::remove_file(self.path(entry.id)).await;
+        true
+    }
+
+    /// Drops every expired file, returns how many were removed.
+    pub async fn remove_expired(&self) -> usize {
+        let now = Instant::now();
+        let removed = self.remove_matching(|_, entry| entry.is_expired(now)).await;
+        self.expired.fetch_add(removed as u64, Ordering::Relaxed);
+        removed
+    }
+
+    /// Drops every file whose name starts with `prefix`, returns how many
+    /// were removed.
+    pub async fn remove_prefix(&self, prefix: &str) -> usize {
+        self.remove_matching(|file, _| file.starts_with(prefix))
+            .await
+    }
+
+    pub async fn clear(&self) -> usize {
+        self.remove_matching(|_, _| true).await
+    }
+
+    async fn remove_matching<F>(&self, matches: F) -> usize
+    where
+        F: Fn(&str, &DiskEntry) -> bool,
+    {
+        let ids = {
+            let mut index = self.index.lock().await;
+            let files = index
+                .data
+                .iter()
+                .filter(|(file, entry)| matches(file, entry))
+                .map(|(file, _)| file.clone())
+                .collect::<Vec<_>>();
+            let mut ids = Vec::with_capacity(files.len());
+            for file in files {
+                if let Some(entry) = index.data.pop(&file) {
+                    index.cur_size -= entry.size;
+                    ids.push(entry.id);
+                }
+            }
+            ids
+        };
+        for id in ids.iter() {
+            let _ = tokio::fs::remove_file(self.path(*id)).await;
+        }
+        ids.len()
+    }
+
+    /// Number of files dropped because their TTL passed.
+    pub fn expired(&self) -> u64 {
+        self.expired.load(Ordering::Relaxed)
+    }
+
+    pub async fn len(&self) -> usize {
+        self.index.lock().await.data.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    pub async fn size(&self) -> usize {
+        self.index.lock().await.cur_size
+    }
+}
+
+/// Writes entries evicted from memory to the disk tier.
+pub(crate) async fn spill(disk: &DiskCache, name: &str, evicted: Vec<Evicted>) {
+    for Evicted {
+        file,
+        data,
+        expires,
+    } in evicted
+    {
+        if let Err(e) = disk.put(&file, data, expires).await {
+            log::warn!("File disk cache [{name}] failed to write {file}: {e}");
+        }
+    }
+}
+
+/// Reads a file that missed in memory from the disk tier, moving it back
+/// into `cache` once it became hot.
+pub(crate) async fn read_through<C: FileCache>(
+    cache: &C,
+    disk: &DiskCache,
+    file: &str,
+    range: Option<Range<usize>>,
+) -> Option<Bytes> {
+    let read = disk.get(file, range.clone()).await?;
+    if !read.promote {
+        return Some(read.data);
+    }
+    disk.remove(file).await;
+    let ret = match read.expires {
+        Some(expires) => {
+            let ttl = expires.saturating_duration_since(Instant::now());
+            cache
+                .set_with_ttl("disk", file, read.data.clone(), ttl)
+                .await
+        }
+        None => cache.set("disk", file, read.data.clone()).await,
+    };
+    if let Err(e) = ret {
+        log::warn!(
+            "File disk cache [{}] failed to promote {file}: {e}",
+            cache.name()
+        );
+    }
+    match range {
+        Some(range) => crate::slice(&read.data, range),
+        None => Some(read.data),
+    }
+}
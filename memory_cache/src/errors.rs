@@ -1,10 +1,59 @@
+use arrow::error::ArrowError;
 use snafu::Snafu;
+use std::{path::PathBuf, sync::Arc};
 
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub))]
 pub enum MyError {
     #[snafu(display("Failed to open file: {}", path))]
-    OpenFile { path: String, source: std::io::Error },
+    OpenFile {
+        path: String,
+        source: std::io::Error,
+    },
     #[snafu(display("Failed to read data from file: {}", path))]
-    ReadData { path: String, source: std::io::Error },
-}
\ No newline at end of file
+    ReadData {
+        path: String,
+        source: std::io::Error,
+    },
+    #[snafu(display("Failed to read Arrow data from file: {}", path))]
+    ReadArrow { path: String, source: ArrowError },
+    #[snafu(display("Failed to write output: {}", source))]
+    WriteOutput { source: ArrowError },
+}
+
+/// Error returned by a `get_or_load` loader.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub))]
+pub enum CacheError {
+    #[snafu(display(
+        "File {} of {} bytes exceeds the size {} of file memory cache [{}]",
+        file,
+        size,
+        max_size,
+        name
+    ))]
+    EntryTooLarge {
+        name: String,
+        file: String,
+        size: usize,
+        max_size: usize,
+    },
+    #[snafu(display("File memory cache [{}] is corrupt: {}", name, reason))]
+    Corrupt { name: String, reason: String },
+    #[snafu(display("Failed to load file {}: {}", file, source))]
+    LoaderFailed {
+        file: String,
+        source: Arc<dyn std::error::Error + Send + Sync>,
+    },
+    #[snafu(display("Invalid cache snapshot {}: {}", path.display(), reason))]
+    Snapshot { path: PathBuf, reason: String },
+    #[snafu(display("Failed to decode Arrow IPC stream {}: {}", file, source))]
+    Decode { file: String, source: ArrowError },
+    #[snafu(display("Failed to access cache file {}: {}", path.display(), source))]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
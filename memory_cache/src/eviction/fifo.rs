@@ -0,0 +1,49 @@
+use hashlink::LinkedHashMap;
+
+use super::EvictionPolicy;
+
+pub struct Fifo {
+    data: LinkedHashMap<String, usize>,
+}
+
+impl Default for Fifo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fifo {
+    pub fn new() -> Fifo {
+        Fifo {
+            data: LinkedHashMap::new(),
+        }
+    }
+}
+
+impl EvictionPolicy for Fifo {
+    fn insert(&mut self, key: &str, size: usize) {
+        self.data.insert(key.to_string(), size);
+    }
+
+    fn access(&mut self, _key: &str) {}
+
+    fn contains(&self, key: &str) -> bool {
+        self.data.contains_key(key)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<usize> {
+        self.data.remove(key)
+    }
+
+    fn evict(&mut self) -> Option<(String, usize)> {
+        self.data.pop_front()
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.data.keys().cloned().collect()
+    }
+
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+}
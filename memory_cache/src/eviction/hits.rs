@@ -0,0 +1,85 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+
+use super::EvictionPolicy;
+
+/// Cache hits recorded without locking the eviction policy, replayed into
+/// it by the next writer before it evicts anything.
+///
+/// Every key keeps its number of hits and the sequence number of its last
+/// one, replaying them in that order gives the policy the recency and
+/// frequency it would have seen hit by hit.
+#[derive(Default)]
+pub(crate) struct Hits {
+    seq: AtomicU64,
+    keys: DashMap<String, (u64, u32), ahash::RandomState>,
+}
+
+impl Hits {
+    pub fn record(&self, key: &str) {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let mut hit = match self.keys.get_mut(key) {
+            Some(hit) => hit,
+            None => self.keys.entry(key.to_string()).or_insert((seq, 0)),
+        };
+        hit.0 = hit.0.max(seq);
+        hit.1 = hit.1.saturating_add(1);
+    }
+
+    /// Replays the hits recorded so far into `policy`, keys which are no
+    /// longer tracked are skipped by the policy.
+    pub fn replay(&self, policy: &mut dyn EvictionPolicy) {
+        if self.keys.is_empty() {
+            return;
+        }
+        let mut hits = Vec::with_capacity(self.keys.len());
+        self.keys.retain(|key, &mut (seq, n)| {
+            hits.push((seq, key.clone(), n));
+            false
+        });
+        hits.sort_unstable_by_key(|(seq, ..)| *seq);
+        for (_, key, n) in hits {
+            for _ in 0..n {
+                policy.access(&key);
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        self.keys.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Policy;
+
+    #[test]
+    fn test_replay_in_hit_order() {
+        let mut p = Policy::Lru.build();
+        for key in ["a", "b", "c"] {
+            p.insert(key, 1);
+        }
+        let hits = Hits::default();
+        hits.record("b");
+        hits.record("a");
+        hits.record("b");
+        hits.record("gone");
+        hits.replay(p.as_mut());
+        assert_eq!(p.keys(), ["c", "a", "b"]);
+        assert!(hits.keys.is_empty());
+
+        let mut p = Policy::Lfu.build();
+        for key in ["a", "b", "c"] {
+            p.insert(key, 1);
+        }
+        for _ in 0..3 {
+            hits.record("a");
+        }
+        hits.record("c");
+        hits.replay(p.as_mut());
+        assert_eq!(p.keys(), ["b", "c", "a"]);
+    }
+}
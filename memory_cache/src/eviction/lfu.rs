@@ -0,0 +1,88 @@
+use hashbrown::HashMap;
+use std::collections::BTreeMap;
+
+use super::EvictionPolicy;
+
+pub struct Lfu {
+    seq: u64,
+    entries: HashMap<String, LfuEntry>,
+    /// (frequency, last access sequence) -> key, the first item is the victim
+    order: BTreeMap<(u64, u64), String>,
+}
+
+struct LfuEntry {
+    freq: u64,
+    seq: u64,
+    size: usize,
+}
+
+impl Default for Lfu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lfu {
+    pub fn new() -> Lfu {
+        Lfu {
+            seq: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+}
+
+impl EvictionPolicy for Lfu {
+    fn insert(&mut self, key: &str, size: usize) {
+        let seq = self.next_seq();
+        if let Some(old) = self
+            .entries
+            .insert(key.to_string(), LfuEntry { freq: 1, seq, size })
+        {
+            self.order.remove(&(old.freq, old.seq));
+        }
+        self.order.insert((1, seq), key.to_string());
+    }
+
+    fn access(&mut self, key: &str) {
+        let seq = self.next_seq();
+        let Some(entry) = self.entries.get_mut(key) else {
+            return;
+        };
+        let Some(key) = self.order.remove(&(entry.freq, entry.seq)) else {
+            return;
+        };
+        entry.freq += 1;
+        entry.seq = seq;
+        self.order.insert((entry.freq, entry.seq), key);
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<usize> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&(entry.freq, entry.seq));
+        Some(entry.size)
+    }
+
+    fn evict(&mut self) -> Option<(String, usize)> {
+        let (_, key) = self.order.pop_first()?;
+        let entry = self.entries.remove(&key)?;
+        Some((key, entry.size))
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.order.values().cloned().collect()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
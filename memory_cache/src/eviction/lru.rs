@@ -0,0 +1,51 @@
+use lru::LruCache;
+
+use super::EvictionPolicy;
+
+pub struct Lru {
+    data: LruCache<String, usize>,
+}
+
+impl Default for Lru {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lru {
+    pub fn new() -> Lru {
+        Lru {
+            data: LruCache::unbounded(),
+        }
+    }
+}
+
+impl EvictionPolicy for Lru {
+    fn insert(&mut self, key: &str, size: usize) {
+        self.data.put(key.to_string(), size);
+    }
+
+    fn access(&mut self, key: &str) {
+        self.data.promote(key);
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.data.contains(key)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<usize> {
+        self.data.pop(key)
+    }
+
+    fn evict(&mut self) -> Option<(String, usize)> {
+        self.data.pop_lru()
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.data.iter().rev().map(|(key, _)| key.clone()).collect()
+    }
+
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+}
@@ -0,0 +1,168 @@
+mod fifo;
+mod hits;
+mod lfu;
+mod lru;
+mod s3fifo;
+
+pub use self::fifo::Fifo;
+pub(crate) use self::hits::Hits;
+pub use self::lfu::Lfu;
+pub use self::lru::Lru;
+pub use self::s3fifo::S3Fifo;
+
+/// Decides which entry leaves the cache when it is full.
+///
+/// A policy only tracks keys and their sizes, the cached bytes are kept by
+/// the backends themselves.
+pub trait EvictionPolicy: Send + Sync {
+    /// Adds a key which is not tracked yet.
+    fn insert(&mut self, key: &str, size: usize);
+    /// Records a cache hit of `key`.
+    fn access(&mut self, key: &str);
+    fn contains(&self, key: &str) -> bool;
+    /// Stops tracking `key`, returning its size.
+    fn remove(&mut self, key: &str) -> Option<usize>;
+    /// Picks the next victim and stops tracking it.
+    fn evict(&mut self) -> Option<(String, usize)>;
+    /// Tracked keys ordered from the next victim to the hottest one.
+    fn keys(&self) -> Vec<String>;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Eviction policies selectable in [`crate::CacheConfig`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Least recently used entry first.
+    Lru,
+    /// Oldest inserted entry first, hits are ignored.
+    Fifo,
+    /// Least frequently used entry first, ties broken by recency.
+    Lfu,
+    /// Scan resistant S3-FIFO: a small probationary queue in front of the
+    /// main queue plus a ghost queue of recently evicted keys.
+    S3Fifo,
+}
+
+impl Policy {
+    pub fn build(&self) -> Box<dyn EvictionPolicy> {
+        match self {
+            Policy::Lru => Box::new(Lru::new()),
+            Policy::Fifo => Box::new(Fifo::new()),
+            Policy::Lfu => Box::new(Lfu::new()),
+            Policy::S3Fifo => Box::new(S3Fifo::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(policy: &mut dyn EvictionPolicy) {
+        for key in ["a", "b", "c", "d"] {
+            policy.insert(key, 1);
+        }
+    }
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let mut p = Policy::Lru.build();
+        fill(p.as_mut());
+        p.access("a");
+        assert_eq!(p.evict(), Some(("b".to_string(), 1)));
+        assert_eq!(p.evict(), Some(("c".to_string(), 1)));
+        assert_eq!(p.evict(), Some(("d".to_string(), 1)));
+        assert_eq!(p.evict(), Some(("a".to_string(), 1)));
+        assert_eq!(p.evict(), None);
+    }
+
+    #[test]
+    fn test_fifo_ignores_access() {
+        let mut p = Policy::Fifo.build();
+        fill(p.as_mut());
+        p.access("a");
+        assert_eq!(p.evict(), Some(("a".to_string(), 1)));
+        assert_eq!(p.remove("c"), Some(1));
+        assert_eq!(p.evict(), Some(("b".to_string(), 1)));
+        assert_eq!(p.evict(), Some(("d".to_string(), 1)));
+        assert!(p.is_empty());
+    }
+
+    #[test]
+    fn test_lfu_evicts_least_frequently_used() {
+        let mut p = Policy::Lfu.build();
+        fill(p.as_mut());
+        p.access("a");
+        p.access("a");
+        p.access("b");
+        p.access("d");
+        assert_eq!(p.evict(), Some(("c".to_string(), 1)));
+        assert_eq!(p.evict(), Some(("b".to_string(), 1)));
+        assert_eq!(p.evict(), Some(("d".to_string(), 1)));
+        assert_eq!(p.evict(), Some(("a".to_string(), 1)));
+    }
+
+    #[test]
+    fn test_s3fifo_keeps_hot_keys_through_scan() {
+        let mut p = Policy::S3Fifo.build();
+        for i in 0..10 {
+            p.insert(&format!("hot{i}"), 1);
+            p.access(&format!("hot{i}"));
+        }
+        // a one-hit scan only churns the small queue
+        for i in 0..100 {
+            p.insert(&format!("scan{i}"), 1);
+            while p.len() > 20 {
+                let (key, _) = p.evict().unwrap();
+                assert!(key.starts_with("scan"), "evicted {key}");
+            }
+        }
+        for i in 0..10 {
+            assert!(p.contains(&format!("hot{i}")));
+        }
+    }
+
+    #[test]
+    fn test_keys_follow_eviction_order() {
+        for policy in [Policy::Lru, Policy::Fifo, Policy::Lfu] {
+            let mut p = policy.build();
+            fill(p.as_mut());
+            p.access("b");
+            p.access("b");
+            p.access("a");
+            let keys = p.keys();
+            let mut evicted = Vec::new();
+            while let Some((key, _)) = p.evict() {
+                evicted.push(key);
+            }
+            assert_eq!(keys, evicted, "{policy:?}");
+        }
+        // without hits S3-FIFO evicts the small queue in insertion order
+        let mut p = Policy::S3Fifo.build();
+        fill(p.as_mut());
+        assert_eq!(p.keys(), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_policies_track_len_and_remove() {
+        for policy in [Policy::Lru, Policy::Fifo, Policy::Lfu, Policy::S3Fifo] {
+            let mut p = policy.build();
+            fill(p.as_mut());
+            assert_eq!(p.len(), 4);
+            assert!(p.contains("b"));
+            assert_eq!(p.remove("b"), Some(1));
+            assert_eq!(p.remove("b"), None);
+            assert!(!p.contains("b"));
+            assert_eq!(p.len(), 3);
+            let mut evicted = Vec::new();
+            while let Some((key, _)) = p.evict() {
+                evicted.push(key);
+            }
+            evicted.sort();
+            assert_eq!(evicted, ["a", "c", "d"], "{policy:?}");
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use hashbrown::HashMap;
+use std::collections::VecDeque;
+
+use super::EvictionPolicy;
+
+/// Maximum frequency counted per entry.
+const MAX_FREQ: u8 = 3;
+/// Share of the tracked bytes reserved for the small queue, in percent.
+const SMALL_RATIO: usize = 10;
+
+/// S3-FIFO, see <https://s3fifo.com>.
+///
+/// New keys enter the small queue and are only moved to the main queue if
+/// they were hit before reaching its head, so one-hit wonders from scans
+/// never displace the working set. Keys evicted from the small queue are
+/// remembered in the ghost queue and go straight to the main queue when they
+/// come back.
+///
+/// Queues are cleaned lazily: a queued key is stale when its generation no
+/// longer matches the one recorded in `entries`.
+pub struct S3Fifo {
+    gen: u64,
+    entries: HashMap<String, S3Entry>,
+    small: VecDeque<(String, u64)>,
+    main: VecDeque<(String, u64)>,
+    small_size: usize,
+    main_size: usize,
+    ghost: VecDeque<(String, u64)>,
+    ghost_keys: HashMap<String, u64>,
+}
+
+struct S3Entry {
+    gen: u64,
+    size: usize,
+    freq: u8,
+    in_main: bool,
+}
+
+impl Default for S3Fifo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl S3Fifo {
+    pub fn new() -> S3Fifo {
+        S3Fifo {
+            gen: 0,
+            entries: HashMap::new(),
+            small: VecDeque::new(),
+            main: VecDeque::new(),
+            small_size: 0,
+            main_size: 0,
+            ghost: VecDeque::new(),
+            ghost_keys: HashMap::new(),
+        }
+    }
+
+    fn next_gen(&mut self) -> u64 {
+        self.gen += 1;
+        self.gen
+    }
+
+    fn is_live(&self, key: &str, gen: u64) -> bool {
+        self.entries.get(key).is_some_and(|e| e.gen == gen)
+    }
+
+    fn remember(&mut self, key: String) {
+        let gen = self.next_gen();
+        self.ghost_keys.insert(key.clone(), gen);
+        self.ghost.push_back((key, gen));
+        // the ghost queue remembers about as many keys as the main queue holds
+        let limit = self.entries.len().max(1);
+        while self.ghost_keys.len() > limit {
+            let Some((key, gen)) = self.ghost.pop_front() else {
+                break;
+            };
+            if self.ghost_keys.get(&key) == Some(&gen) {
+                self.ghost_keys.remove(&key);
+            }
+        }
+    }
+
+    /// Drops stale keys left in the queues by `remove`.
+    fn compact(&mut self) {
+        let entries = &self.entries;
+        let is_live = |(key, gen): &(String, u64)| entries.get(key).is_some_and(|e| e.gen == *gen);
+        self.small.retain(is_live);
+        self.main.retain(is_live);
+    }
+
+    fn evict_small(&mut self) -> Option<(String, usize)> {
+        while let Some((key, gen)) = self.small.pop_front() {
+            if !self.is_live(&key, gen) {
+                continue;
+            }
+            let entry = self.entries.get_mut(&key).unwrap();
+            self.small_size -= entry.size;
+            if entry.freq > 0 {
+                entry.freq = 0;
+                entry.in_main = true;
+                self.main_size += entry.size;
+                self.main.push_back((key, gen));
+                continue;
+            }
+            let entry = self.entries.remove(&key).unwrap();
+            self.remember(key.clone());
+            return Some((key, entry.size));
+        }
+        None
+    }
+
+    fn evict_main(&mut self) -> Option<(String, usize)> {
+        while let Some((key, gen)) = self.main.pop_front() {
+            if !self.is_live(&key, gen) {
+                continue;
+            }
+            let entry = self.entries.get_mut(&key).unwrap();
+            if entry.freq > 0 {
+                entry.freq -= 1;
+                self.main.push_back((key, gen));
+                continue;
+            }
+            let entry = self.entries.remove(&key).unwrap();
+            self.main_size -= entry.size;
+            return Some((key, entry.size));
+        }
+        None
+    }
+}
+
+impl EvictionPolicy for S3Fifo {
+    fn insert(&mut self, key: &str, size: usize) {
+        self.remove(key);
+        let gen = self.next_gen();
+        let in_main = self.ghost_keys.remove(key).is_some();
+        self.entries.insert(
+            key.to_string(),
+            S3Entry {
+                gen,
+                size,
+                freq: 0,
+                in_main,
+            },
+        );
+        if in_main {
+            self.main_size += size;
+            self.main.push_back((key.to_string(), gen));
+        } else {
+            self.small_size += size;
+            self.small.push_back((key.to_string(), gen));
+        }
+    }
+
+    fn access(&mut self, key: &str) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.freq = (entry.freq + 1).min(MAX_FREQ);
+        }
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<usize> {
+        let entry = self.entries.remove(key)?;
+        if entry.in_main {
+            self.main_size -= entry.size;
+        } else {
+            self.small_size -= entry.size;
+        }
+        if self.small.len() + self.main.len() > 2 * self.entries.len() + 64 {
+            self.compact();
+        }
+        Some(entry.size)
+    }
+
+    fn evict(&mut self) -> Option<(String, usize)> {
+        let total = self.small_size + self.main_size;
+        if self.small_size * 100 > total * SMALL_RATIO || self.main_size == 0 {
+            if let Some(item) = self.evict_small() {
+                return Some(item);
+            }
+        }
+        self.evict_main().or_else(|| self.evict_small())
+    }
+
+    /// Follows the queues instead of replaying the eviction, so keys hit
+    /// while in the small queue are listed before the main queue although
+    /// they would be moved to it.
+    fn keys(&self) -> Vec<String> {
+        self.small
+            .iter()
+            .chain(self.main.iter())
+            .filter(|(key, gen)| self.is_live(key, *gen))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
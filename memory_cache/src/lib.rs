@@ -1,4 +1,117 @@
+use std::{future::Future, ops::Range, path::Path, time::Duration};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+
+pub mod batch;
+pub mod config;
+pub mod disk;
+pub mod errors;
+pub mod eviction;
+mod load;
 pub mod memory_v1;
-pub mod memory_v2;
-pub mod memory_v3;
-pub mod errors;
\ No newline at end of file
+pub mod pressure;
+pub mod range;
+pub mod sharded;
+mod snapshot;
+pub mod stats;
+mod sysinfo;
+mod ttl;
+mod verify;
+
+pub use batch::{BatchCache, Batches};
+pub use config::{CacheConfig, DiskConfig};
+pub use errors::{BoxError, CacheError};
+pub use eviction::{EvictionPolicy, Policy};
+pub use pressure::PressureConfig;
+pub use range::{RangeCache, RangeLookup};
+pub use sharded::ShardedCache;
+pub use snapshot::SnapshotEntry;
+pub use stats::{encode_prometheus, CacheStats};
+
+type Result<T> = std::result::Result<T, CacheError>;
+
+/// Common interface of the file cache backends.
+///
+/// Sizes are accounted as `file.len() + data.len()` for every entry.
+#[async_trait]
+pub trait FileCache: Sync + Send + 'static {
+    fn name(&self) -> &str;
+    async fn get(&self, file: &str) -> Option<Bytes>;
+    /// Returns `None` if the file is not cached or `range` is out of bounds.
+    async fn get_range(&self, file: &str, range: Range<usize>) -> Option<Bytes>;
+    /// Caching a file that already exists is a no-op. The entry expires
+    /// after the default TTL of the cache, if one is configured.
+    async fn set(&self, session_id: &str, file: &str, data: Bytes) -> Result<()>;
+    /// Like [`FileCache::set`] but the entry expires after `ttl`.
+    async fn set_with_ttl(
+        &self,
+        session_id: &str,
+        file: &str,
+        data: Bytes,
+        ttl: Duration,
+    ) -> Result<()>;
+    /// Returns the cached file, or caches the output of `loader` on a miss.
+    ///
+    /// Concurrent misses for the same file share a single run of `loader`,
+    /// its error is returned to every waiter as [`CacheError::LoaderFailed`]
+    /// and nothing gets cached.
+    async fn get_or_load<F, Fut>(&self, file: &str, loader: F) -> Result<Bytes>
+    where
+        Self: Sized,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = std::result::Result<Bytes, BoxError>> + Send + 'static;
+    /// Checks memory and, if configured, the disk tier.
+    async fn exist(&self, file: &str) -> bool;
+    /// Returns `file` if it is held in memory, without counting the read in
+    /// the stats or for the eviction policy.
+    async fn peek(&self, file: &str) -> Option<Bytes>;
+    /// Returns `true` if the file was cached.
+    async fn remove(&self, file: &str) -> bool;
+    /// Drops every file whose name starts with `prefix`, e.g. all files of a
+    /// stream, returns how many were removed.
+    async fn remove_prefix(&self, prefix: &str) -> usize;
+    /// Drops every file from memory and disk.
+    async fn clear(&self);
+    /// Drops every expired entry, returns how many were removed.
+    async fn remove_expired(&self) -> usize;
+    /// Changes the memory budget, releasing entries right away when the
+    /// cache holds more than `max_size`.
+    async fn set_capacity(&self, max_size: usize);
+    /// Number of entries held in memory.
+    async fn len(&self) -> usize;
+    async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+    async fn stats(&self) -> CacheStats;
+    /// Checks that the bookkeeping of the cache matches the cached data,
+    /// returns [`CacheError::Corrupt`] naming the first broken invariant.
+    ///
+    /// The cache is locked while checking, it is meant for tests and
+    /// debugging.
+    async fn verify(&self) -> Result<()>;
+    /// Entries held in memory ordered from the hottest to the coldest one,
+    /// expired entries are left out.
+    async fn entries(&self) -> Vec<SnapshotEntry>;
+    /// Writes the entries held in memory to `path`, returns how many were
+    /// written. The disk tier is not part of the snapshot.
+    async fn snapshot(&self, path: &Path) -> Result<usize> {
+        snapshot::write(path, self.entries().await).await
+    }
+    /// Loads a snapshot written by [`FileCache::snapshot`], e.g. at startup,
+    /// returns how many entries were cached.
+    ///
+    /// With `max_bytes` only the hottest entries up to that size are loaded.
+    /// Entries keep the TTL they had when the snapshot was taken, counting the
+    /// time in between.
+    async fn restore(&self, path: &Path, max_bytes: Option<usize>) -> Result<usize> {
+        snapshot::restore(self, path, max_bytes).await
+    }
+}
+
+pub(crate) fn slice(data: &Bytes, range: Range<usize>) -> Option<Bytes> {
+    if range.start > range.end || range.end > data.len() {
+        return None;
+    }
+    Some(data.slice(range))
+}
@@ -0,0 +1,175 @@
+use bytes::Bytes;
+use futures::{
+    channel::oneshot,
+    future::{BoxFuture, FutureExt, Shared},
+};
+use hashbrown::HashMap;
+use snafu::ResultExt;
+use std::{
+    any::Any,
+    error::Error,
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    errors::{BoxError, LoaderFailedSnafu},
+    FileCache, Result,
+};
+
+type SharedLoad =
+    Shared<BoxFuture<'static, std::result::Result<Bytes, Arc<dyn Error + Send + Sync>>>>;
+
+/// Loads currently running for missing files of a cache instance.
+///
+/// A load is shared by every caller asking for the same file until it
+/// finishes, then it is dropped whatever the outcome, so a failed or
+/// panicked load is retried by the next caller.
+#[derive(Clone, Default)]
+pub(crate) struct Loading {
+    inflight: Arc<Mutex<HashMap<String, SharedLoad>>>,
+}
+
+impl Loading {
+    fn finish(&self, file: &str) {
+        self.inflight.lock().unwrap().remove(file);
+    }
+}
+
+/// Returns the cached file, or runs `loader` once for all concurrent callers
+/// and caches its result.
+pub(crate) async fn get_or_load<C, F, Fut>(
+    cache: &C,
+    loading: &Loading,
+    file: &str,
+    loader: F,
+) -> Result<Bytes>
+where
+    C: FileCache + Clone,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = std::result::Result<Bytes, BoxError>> + Send + 'static,
+{
+    if let Some(data) = cache.get(file).await {
+        return Ok(data);
+    }
+    join(cache, loading, file, loader).await
+}
+
+/// Waits for the running load of `file`, or starts one.
+async fn join<C, F, Fut>(cache: &C, loading: &Loading, file: &str, loader: F) -> Result<Bytes>
+where
+    C: FileCache + Clone,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = std::result::Result<Bytes, BoxError>> + Send + 'static,
+{
+    let (load, start) = {
+        let mut inflight = loading.inflight.lock().unwrap();
+        match inflight.get(file) {
+            Some(load) => (load.clone(), None),
+            None => {
+                let (tx, rx) = oneshot::channel();
+                let load = run(cache.clone(), loading.clone(), file.to_string(), rx);
+                inflight.insert(file.to_string(), load.clone());
+                (load, Some(tx))
+            }
+        }
+    };
+    // the loader is called without holding the lock, callers joining in the
+    // meantime wait for it on the channel
+    if let Some(tx) = start {
+        let start = match cache.peek(file).await {
+            // a load which finished after this caller missed the cache is no
+            // longer in `inflight`, but it cached the file before leaving it
+            Some(data) => Start::Cached(data),
+            None => match std::panic::catch_unwind(AssertUnwindSafe(loader)) {
+                Ok(load) => Start::Load(load.boxed()),
+                Err(panic) => Start::Load(futures::future::err(panicked(&panic)).boxed()),
+            },
+        };
+        let _ = tx.send(start);
+    }
+    load.await.context(LoaderFailedSnafu { file })
+}
+
+/// What the caller starting a load hands over to it.
+enum Start {
+    Cached(Bytes),
+    Load(BoxFuture<'static, std::result::Result<Bytes, BoxError>>),
+}
+
+fn run<C: FileCache>(
+    cache: C,
+    loading: Loading,
+    file: String,
+    start: oneshot::Receiver<Start>,
+) -> SharedLoad {
+    async move {
+        let ret = match start.await {
+            Ok(Start::Cached(data)) => Ok(data),
+            Ok(Start::Load(load)) => match AssertUnwindSafe(load).catch_unwind().await {
+                Ok(Ok(data)) => {
+                    if let Err(e) = cache.set("load", &file, data.clone()).await {
+                        log::warn!(
+                            "File memory cache [{}] failed to cache loaded {file}: {e}",
+                            cache.name()
+                        );
+                    }
+                    Ok(data)
+                }
+                Ok(Err(e)) => Err(e),
+                Err(panic) => Err(panicked(&panic)),
+            },
+            Err(_) => Err("the caller starting the load was cancelled".into()),
+        };
+        loading.finish(&file);
+        ret.map_err(Arc::from)
+    }
+    .boxed()
+    .shared()
+}
+
+fn panicked(panic: &Box<dyn Any + Send>) -> BoxError {
+    let msg = if let Some(msg) = panic.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = panic.downcast_ref::<String>() {
+        msg
+    } else {
+        "unknown panic"
+    };
+    format!("loader panicked: {msg}").into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_v1;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn join_after_finish() {
+        let cache = memory_v1::FileCache::new("test");
+        let loading = Loading::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let loader = |calls: Arc<AtomicUsize>| {
+            move || async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(Bytes::from("aaaa"))
+            }
+        };
+        let data = get_or_load(&cache, &loading, "a", loader(calls.clone()))
+            .await
+            .unwrap();
+        assert_eq!(data, Bytes::from("aaaa"));
+
+        // a caller which missed the cache before the load above cached the
+        // file, and reaches `inflight` after the load left it
+        assert!(loading.inflight.lock().unwrap().is_empty());
+        let data = join(&cache, &loading, "a", loader(calls.clone()))
+            .await
+            .unwrap();
+        assert_eq!(data, Bytes::from("aaaa"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(loading.inflight.lock().unwrap().is_empty());
+    }
+}
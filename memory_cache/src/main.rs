@@ -1,39 +1,169 @@
-use arrow::{ipc::reader::StreamReader, util::pretty};
-use memory_cache::errors::MyError;
-use memory_cache::errors::{OpenFileSnafu, ReadDataSnafu};
+use arrow::{
+    array::Array,
+    error::ArrowError,
+    ipc::reader::{FileReader, StreamReader},
+    record_batch::RecordBatch,
+    util::pretty,
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use memory_cache::errors::{
+    MyError, OpenFileSnafu, ReadArrowSnafu, ReadDataSnafu, WriteOutputSnafu,
+};
+use memory_cache::Batches;
 use snafu::ResultExt; // for the context method
 use std::fs::File;
-use std::io::Read;
-
-#[tokio::main]
-async fn main() -> Result<(), anyhow::Error> {
-    let a = std::path::Path::new("../Cargo.toml.rs");
-    println!("to_strs: {:?}", a.to_str().unwrap().to_string());
-    println!("display: {:?}", a.display().to_string());
-    let file = std::env::args().nth(1).unwrap_or_default();
-    if file.is_empty() {
-        println!("Usage: arrow_read <file>");
-        return Ok(());
+use std::io::{BufReader, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// Magic bytes at the start of the IPC file format, streams have none.
+const IPC_FILE_MAGIC: &[u8; 6] = b"ARROW1";
+
+/// Inspect Arrow IPC files and streams.
+#[derive(Parser)]
+#[clap(name = "arrow_read", version)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the schema
+    Schema { file: PathBuf },
+    /// Print the first rows
+    Head {
+        file: PathBuf,
+        /// Number of rows
+        #[clap(short = 'n', long, default_value_t = 10)]
+        rows: usize,
+        #[clap(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Print the row count and the null count and size of every column
+    Stats { file: PathBuf },
+    /// Print all rows
+    Cat {
+        file: PathBuf,
+        #[clap(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    /// One JSON object per row
+    Json,
+    Table,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli.command) {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
     }
-    let file = std::fs::File::open(file).unwrap();
-    let stream_reader = StreamReader::try_new(&file, None)?;
-    let mut batches = vec![];
-    for read_result in stream_reader {
-        let record_batch = read_result?;
-        batches.push(record_batch);
+}
+
+fn run(command: Command) -> Result<(), MyError> {
+    match command {
+        Command::Schema { file } => {
+            let data = read(&file)?;
+            for field in data.schema.fields() {
+                let nullable = if field.is_nullable() { "" } else { " not null" };
+                println!("{}: {}{nullable}", field.name(), field.data_type());
+            }
+            Ok(())
+        }
+        Command::Head { file, rows, format } => {
+            let data = read(&file)?;
+            let head = data
+                .slice(0..rows.min(data.num_rows()))
+                .expect("range within the file");
+            print(&head.batches, format)
+        }
+        Command::Stats { file } => stats(&file),
+        Command::Cat { file, format } => print(&read(&file)?.batches, format),
+    }
+}
+
+/// Reads an IPC file or stream, the format is told apart by its magic bytes.
+fn read(path: &Path) -> Result<Batches, MyError> {
+    let path_str = path.display().to_string();
+    let mut file = File::open(path).context(OpenFileSnafu { path: &path_str })?;
+    let mut magic = [0u8; 6];
+    let is_file = file.read_exact(&mut magic).is_ok() && &magic == IPC_FILE_MAGIC;
+    file.rewind().context(ReadDataSnafu { path: &path_str })?;
+
+    let (schema, batches) = if is_file {
+        let reader = FileReader::try_new(file, None).context(ReadArrowSnafu { path: &path_str })?;
+        (reader.schema(), reader.collect::<Result<Vec<_>, _>>())
+    } else {
+        let reader = StreamReader::try_new(BufReader::new(file), None)
+            .context(ReadArrowSnafu { path: &path_str })?;
+        (reader.schema(), reader.collect::<Result<Vec<_>, _>>())
+    };
+    let batches = batches.context(ReadArrowSnafu { path: &path_str })?;
+    Ok(Batches { schema, batches })
+}
+
+fn stats(path: &Path) -> Result<(), MyError> {
+    let data = read(path)?;
+    let file_size = std::fs::metadata(path)
+        .context(ReadDataSnafu {
+            path: path.display().to_string(),
+        })?
+        .len();
+    println!("rows: {}", data.num_rows());
+    println!("batches: {}", data.batches.len());
+    println!("file size: {file_size} bytes");
+    println!("memory size: {} bytes", data.memory_size());
+    println!();
+
+    let width = data
+        .schema
+        .fields()
+        .iter()
+        .map(|f| f.name().len())
+        .max()
+        .unwrap_or_default()
+        .max("column".len());
+    println!("{:<width$}  {:>12}  {:>14}", "column", "nulls", "bytes");
+    for (i, field) in data.schema.fields().iter().enumerate() {
+        let columns = data.batches.iter().map(|b| b.column(i));
+        let (nulls, bytes) = columns.fold((0, 0), |(nulls, bytes), col| {
+            (
+                nulls + col.null_count(),
+                bytes + col.get_array_memory_size(),
+            )
+        });
+        println!("{:<width$}  {nulls:>12}  {bytes:>14}", field.name());
     }
-    // print record batches as table
-    pretty::print_batches(&batches)?;
     Ok(())
 }
 
-fn _read_file(path: &str) -> Result<String, MyError> {
-    let mut file = File::open(path).context(OpenFileSnafu {
-        path: path.to_string(),
-    })?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).context(ReadDataSnafu {
-        path: path.to_string(),
-    })?;
-    Ok(contents)
+fn print(batches: &[RecordBatch], format: Format) -> Result<(), MyError> {
+    let mut stdout = std::io::stdout().lock();
+    match format {
+        Format::Csv => {
+            let mut writer = arrow::csv::Writer::new(stdout);
+            for batch in batches {
+                writer.write(batch).context(WriteOutputSnafu)?;
+            }
+        }
+        Format::Json => {
+            let mut writer = arrow::json::LineDelimitedWriter::new(stdout);
+            for batch in batches {
+                writer.write(batch).context(WriteOutputSnafu)?;
+            }
+            writer.finish().context(WriteOutputSnafu)?;
+        }
+        Format::Table => {
+            let table = pretty::pretty_format_batches(batches).context(WriteOutputSnafu)?;
+            writeln!(stdout, "{table}")
+                .map_err(ArrowError::from)
+                .context(WriteOutputSnafu)?;
+        }
+    }
+    Ok(())
 }
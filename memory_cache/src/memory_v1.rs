@@ -12,28 +12,66 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use async_trait::async_trait;
 use bytes::Bytes;
 use dashmap::DashMap;
-use lru::LruCache;
+use hashbrown::HashMap;
 use once_cell::sync::Lazy;
 use std::{
     cmp::{max, min},
+    future::Future,
     ops::Range,
+    sync::Arc,
+    time::Duration,
 };
-use tokio::sync::RwLock;
+use tokio::{sync::RwLock, task::JoinHandle, time::Instant};
 
-const MAX_SIZE: usize = 1024 * 1024 * 1024;
-const RELEASE_SIZE: usize = 1024 * 1024 * 100;
+use crate::{
+    config::CacheConfig,
+    disk::{self, DiskCache, Evicted},
+    errors::{BoxError, CacheError, CorruptSnafu, EntryTooLargeSnafu},
+    eviction::{EvictionPolicy, Hits, Policy},
+    load::{self, Loading},
+    pressure::{self, PressureConfig},
+    snapshot::SnapshotEntry,
+    stats::{CacheStats, Metrics},
+    ttl, verify, FileCache as _,
+};
 
 pub type RwHashMap<K, V> = DashMap<K, V, ahash::RandomState>;
 
-static FILES: Lazy<RwLock<FileData>> = Lazy::new(|| RwLock::new(FileData::new()));
-static DATA: Lazy<RwHashMap<String, Bytes>> = Lazy::new(Default::default);
+const DEFAULT_POLICY: Policy = Policy::Lru;
+
+static FILES: Lazy<FileCache> = Lazy::new(|| FileCache::new("default"));
+
+/// A cheaply cloneable handle to a file cache instance.
+///
+/// Clones share the same underlying storage, so a handle can be passed to
+/// tasks freely while different instances stay fully independent.
+#[derive(Clone)]
+pub struct FileCache {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    name: String,
+    files: RwLock<FileData>,
+    data: RwHashMap<String, Bytes>,
+    /// Hits waiting to be replayed into the eviction policy, so lookups
+    /// only need a read lock.
+    hits: Hits,
+    disk: Option<DiskCache>,
+    ttl: Option<Duration>,
+    metrics: Metrics,
+    loading: Loading,
+}
 
 pub struct FileData {
     max_size: usize,
+    release_size: usize,
     cur_size: usize,
-    data: LruCache<String, usize>,
+    data: Box<dyn EvictionPolicy>,
+    expires: HashMap<String, Instant>,
 }
 
 impl Default for FileData {
@@ -44,138 +82,631 @@ impl Default for FileData {
 
 impl FileData {
     pub fn new() -> FileData {
-        FileData::with_capacity(MAX_SIZE)
+        let cfg = CacheConfig::default();
+        FileData::with_capacity(cfg.max_size, cfg.release_size)
+    }
+
+    pub fn with_capacity(max_size: usize, release_size: usize) -> FileData {
+        FileData::with_policy(max_size, release_size, DEFAULT_POLICY)
     }
 
-    pub fn with_capacity(max_size: usize) -> FileData {
+    pub fn with_policy(max_size: usize, release_size: usize, policy: Policy) -> FileData {
         FileData {
             max_size,
+            release_size,
             cur_size: 0,
-            data: LruCache::unbounded(),
+            data: policy.build(),
+            expires: HashMap::new(),
         }
     }
 
-    async fn exist(&mut self, file: &str) -> bool {
-        self.data.contains(file)
+    /// Expired entries are reported as missing but left for the next write
+    /// to drop, so lookups only need a read lock.
+    async fn exist(&self, file: &str) -> bool {
+        self.data.contains(file) && !self.is_expired(file, Instant::now())
     }
 
-    async fn get(&self, file: &str, range: Option<Range<usize>>) -> Option<Bytes> {
-        let data = DATA.get(file)?;
-        Some(if let Some(range) = range {
-            data.value().slice(range)
-        } else {
-            data.value().clone()
-        })
+    /// Brings the eviction policy up to date with the hits recorded since,
+    /// before it picks a victim or orders the entries.
+    fn replay(&mut self, hits: &Hits) {
+        hits.replay(self.data.as_mut());
     }
 
+    fn is_expired(&self, file: &str, now: Instant) -> bool {
+        self.expires
+            .get(file)
+            .is_some_and(|expires| *expires <= now)
+    }
+
+    /// Removes `file` if its TTL has passed, returns `true` if it did.
+    async fn expire(&mut self, data_map: &RwHashMap<String, Bytes>, file: &str) -> bool {
+        if !self.is_expired(file, Instant::now()) {
+            return false;
+        }
+        self.remove(data_map, file).await.is_some()
+    }
+
+    async fn remove_expired(&mut self, data_map: &RwHashMap<String, Bytes>) -> usize {
+        let now = Instant::now();
+        let files = self
+            .expires
+            .iter()
+            .filter(|(_, expires)| **expires <= now)
+            .map(|(file, _)| file.clone())
+            .collect::<Vec<_>>();
+        for file in files.iter() {
+            self.remove(data_map, file).await;
+        }
+        files.len()
+    }
+
+    /// Caches `file`, returning the entries evicted to make room for it
+    /// along with the outcome, as they are gone even if caching fails.
     async fn set(
         &mut self,
+        name: &str,
+        data_map: &RwHashMap<String, Bytes>,
         session_id: &str,
         file: &str,
         data: Bytes,
-    ) -> Result<(), anyhow::Error> {
+        expires: Option<Instant>,
+    ) -> (Vec<Evicted>, Result<(), CacheError>) {
         let data_size = file.len() + data.len();
+        if data_size > self.max_size {
+            let err = EntryTooLargeSnafu {
+                name,
+                file,
+                size: data_size,
+                max_size: self.max_size,
+            };
+            return (Vec::new(), err.fail());
+        }
+        let mut evicted = Vec::new();
         if self.cur_size + data_size >= self.max_size {
-            println!(
-                "[session_id {session_id}] File memory cache is full {}/{}, can't cache extra {} bytes",
+            log::debug!(
+                session_id;
+                "File memory cache [{name}] is full {}/{}, can't cache extra {} bytes",
                 self.cur_size,
                 self.max_size,
                 data_size
             );
             // cache is full, need release some space
-            let need_release_size = min(MAX_SIZE, max(RELEASE_SIZE, data_size * 100));
+            let need_release_size = min(self.max_size, max(self.release_size, data_size * 100));
             let mut release_size = 0;
             loop {
-                let item = self.data.pop_lru();
-                if item.is_none() {
-                    println!("[session_id {session_id}] File memory cache is corrupt, it shouldn't be none");
-                    break;
-                }
-                let (key, data_size) = item.unwrap();
+                let Some((key, data_size)) = self.data.evict() else {
+                    if self.cur_size == release_size {
+                        // everything was released
+                        break;
+                    }
+                    // the index lost track of cached bytes
+                    self.cur_size -= release_size;
+                    log::error!(session_id; "File memory cache [{name}] is corrupt, it shouldn't be none");
+                    let err = CorruptSnafu {
+                        name,
+                        reason: format!("{} bytes cached without any entry", self.cur_size),
+                    };
+                    return (evicted, err.fail());
+                };
                 // remove file from data cache
-                DATA.remove(&key);
+                let expires = self.expires.remove(&key);
+                if let Some((file, data)) = data_map.remove(&key) {
+                    evicted.push(Evicted {
+                        file,
+                        data,
+                        expires,
+                    });
+                }
                 release_size += data_size;
                 if release_size >= need_release_size {
                     break;
                 }
             }
             self.cur_size -= release_size;
-            DATA.shrink_to_fit();
+            data_map.shrink_to_fit();
         }
 
         self.cur_size += data_size;
-        self.data.put(file.to_string(), data_size);
+        self.data.insert(file, data_size);
+        if let Some(expires) = expires {
+            self.expires.insert(file.to_string(), expires);
+        }
         // write file into cache
-        DATA.insert(file.to_string(), data);
-        Ok(())
+        data_map.insert(file.to_string(), data);
+        (evicted, Ok(()))
+    }
+
+    async fn remove(&mut self, data_map: &RwHashMap<String, Bytes>, file: &str) -> Option<usize> {
+        let data_size = self.data.remove(file)?;
+        self.expires.remove(file);
+        self.cur_size -= data_size;
+        data_map.remove(file);
+        Some(data_size)
+    }
+
+    async fn remove_prefix(&mut self, data_map: &RwHashMap<String, Bytes>, prefix: &str) -> usize {
+        let files = data_map
+            .iter()
+            .filter(|item| item.key().starts_with(prefix))
+            .map(|item| item.key().clone())
+            .collect::<Vec<_>>();
+        for file in files.iter() {
+            self.remove(data_map, file).await;
+        }
+        files.len()
+    }
+
+    async fn clear(&mut self, data_map: &RwHashMap<String, Bytes>) {
+        while self.data.evict().is_some() {}
+        self.expires.clear();
+        self.cur_size = 0;
+        data_map.clear();
+        data_map.shrink_to_fit();
+    }
+
+    /// Releases entries until the cache fits into `max_size`.
+    async fn set_capacity(
+        &mut self,
+        data_map: &RwHashMap<String, Bytes>,
+        max_size: usize,
+    ) -> Vec<Evicted> {
+        self.max_size = max_size;
+        let mut evicted = Vec::new();
+        if self.cur_size <= max_size {
+            return evicted;
+        }
+        while self.cur_size > max_size {
+            let Some((key, data_size)) = self.data.evict() else {
+                break;
+            };
+            self.cur_size -= data_size;
+            let expires = self.expires.remove(&key);
+            if let Some((file, data)) = data_map.remove(&key) {
+                evicted.push(Evicted {
+                    file,
+                    data,
+                    expires,
+                });
+            }
+        }
+        data_map.shrink_to_fit();
+        evicted
+    }
+
+    async fn verify(
+        &self,
+        name: &str,
+        data_map: &RwHashMap<String, Bytes>,
+    ) -> Result<(), CacheError> {
+        verify::check(
+            verify::Books {
+                name,
+                max_size: self.max_size,
+                cur_size: self.cur_size,
+                index: self.data.as_ref(),
+                expires: &self.expires,
+                data_len: data_map.len(),
+            },
+            |file| data_map.get(file).map(|data| data.len()),
+        )
+    }
+
+    /// Entries from the hottest to the coldest one.
+    async fn entries(&self, data_map: &RwHashMap<String, Bytes>) -> Vec<SnapshotEntry> {
+        let now = Instant::now();
+        self.data
+            .keys()
+            .into_iter()
+            .rev()
+            .filter(|file| !self.is_expired(file, now))
+            .filter_map(|file| {
+                let data = data_map.get(&file)?.value().clone();
+                let ttl = self.expires.get(&file).map(|expires| *expires - now);
+                Some(SnapshotEntry { file, data, ttl })
+            })
+            .collect()
     }
 
     async fn _pop(&mut self) -> Option<String> {
-        if let Some((k, _)) = self.data.pop_lru() {
+        if let Some((k, _)) = self.data.evict() {
             Some(k)
         } else {
             None
         }
     }
 
-    async fn len(&self) -> (usize, usize) {
-        (self.data.len(), DATA.len())
+    async fn len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl Default for FileCache {
+    fn default() -> Self {
+        Self::with_config(CacheConfig::default())
+    }
+}
+
+impl FileCache {
+    pub fn new(name: &str) -> FileCache {
+        FileCache::with_config(CacheConfig::new(name))
+    }
+
+    pub fn with_capacity(name: &str, max_size: usize, release_size: usize) -> FileCache {
+        FileCache::with_config(CacheConfig::with_capacity(name, max_size, release_size))
+    }
+
+    pub fn with_config(cfg: CacheConfig) -> FileCache {
+        FileCache {
+            inner: Arc::new(Inner {
+                files: RwLock::new(FileData::with_policy(
+                    cfg.max_size,
+                    cfg.release_size,
+                    cfg.policy.unwrap_or(DEFAULT_POLICY),
+                )),
+                data: Default::default(),
+                hits: Hits::default(),
+                disk: cfg
+                    .disk
+                    .as_ref()
+                    .map(|disk| DiskCache::new(&cfg.name, disk)),
+                ttl: cfg.default_ttl,
+                metrics: Metrics::default(),
+                loading: Loading::default(),
+                name: cfg.name,
+            }),
+        }
+    }
+
+    /// Spawns a task removing expired entries every `interval`, it stops
+    /// once every handle of the cache was dropped.
+    pub fn start_sweeper(&self, interval: Duration) -> JoinHandle<()> {
+        let inner = Arc::downgrade(&self.inner);
+        ttl::spawn_sweeper(interval, move || {
+            inner.upgrade().map(|inner| FileCache { inner })
+        })
+    }
+
+    /// Spawns a task shrinking the cache while the process RSS is above
+    /// `cfg.high_water` and growing it back once memory frees up, it stops
+    /// once every handle of the cache was dropped.
+    pub fn start_memory_watcher(&self, cfg: PressureConfig) -> JoinHandle<()> {
+        let inner = Arc::downgrade(&self.inner);
+        pressure::spawn_watcher(cfg, move || {
+            inner.upgrade().map(|inner| FileCache { inner })
+        })
+    }
+
+    async fn get_from_disk(&self, file: &str, range: Option<Range<usize>>) -> Option<Bytes> {
+        let is_range = range.is_some();
+        let data = match &self.inner.disk {
+            Some(disk) => disk::read_through(self, disk, file, range).await,
+            None => None,
+        };
+        self.inner.metrics.record_disk_get(data.is_some(), is_range);
+        data
+    }
+
+    async fn insert(
+        &self,
+        session_id: &str,
+        file: &str,
+        data: Bytes,
+        ttl: Option<Duration>,
+    ) -> Result<(), CacheError> {
+        let expires = ttl.map(|ttl| Instant::now() + ttl);
+        let (evicted, ret) = {
+            let mut files = self.inner.files.write().await;
+            files.replay(&self.inner.hits);
+            if files.expire(&self.inner.data, file).await {
+                self.inner.metrics.record_expired(1);
+            }
+            if files.exist(file).await {
+                return Ok(());
+            }
+            files
+                .set(
+                    &self.inner.name,
+                    &self.inner.data,
+                    session_id,
+                    file,
+                    data,
+                    expires,
+                )
+                .await
+        };
+        // entries evicted before a failure are gone from memory all the same
+        self.release(evicted).await;
+        ret?;
+        self.inner.metrics.record_insert();
+        if let Some(disk) = &self.inner.disk {
+            disk.remove(file).await;
+        }
+        Ok(())
+    }
+
+    /// Returns `file` if it is held in memory and not expired, recording the
+    /// hit for the eviction policy.
+    async fn lookup(&self, file: &str) -> Option<Bytes> {
+        {
+            let files = self.inner.files.read().await;
+            if !files.is_expired(file, Instant::now()) {
+                let data = self.inner.data.get(file)?.value().clone();
+                self.inner.hits.record(file);
+                return Some(data);
+            }
+        }
+        let mut files = self.inner.files.write().await;
+        if files.expire(&self.inner.data, file).await {
+            self.inner.metrics.record_expired(1);
+        }
+        None
+    }
+
+    /// Counts entries evicted from memory and spills them to disk.
+    async fn release(&self, evicted: Vec<Evicted>) {
+        let evicted_bytes = evicted.iter().map(|e| e.file.len() + e.data.len()).sum();
+        self.inner
+            .metrics
+            .record_evictions(evicted.len(), evicted_bytes);
+        if let Some(disk) = &self.inner.disk {
+            disk::spill(disk, &self.inner.name, evicted).await;
+        }
+    }
+}
+
+#[async_trait]
+impl crate::FileCache for FileCache {
+    fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    async fn get(&self, file: &str) -> Option<Bytes> {
+        if let Some(data) = self.lookup(file).await {
+            self.inner.metrics.record_get(true, false);
+            return Some(data);
+        }
+        self.get_from_disk(file, None).await
+    }
+
+    async fn get_range(&self, file: &str, range: Range<usize>) -> Option<Bytes> {
+        if let Some(data) = self.lookup(file).await {
+            let data = crate::slice(&data, range);
+            self.inner.metrics.record_get(data.is_some(), true);
+            return data;
+        }
+        self.get_from_disk(file, Some(range)).await
+    }
+
+    async fn set(&self, session_id: &str, file: &str, data: Bytes) -> Result<(), CacheError> {
+        self.insert(session_id, file, data, self.inner.ttl).await
+    }
+
+    async fn set_with_ttl(
+        &self,
+        session_id: &str,
+        file: &str,
+        data: Bytes,
+        ttl: Duration,
+    ) -> Result<(), CacheError> {
+        self.insert(session_id, file, data, Some(ttl)).await
+    }
+
+    async fn get_or_load<F, Fut>(&self, file: &str, loader: F) -> Result<Bytes, CacheError>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<Bytes, BoxError>> + Send + 'static,
+    {
+        load::get_or_load(self, &self.inner.loading, file, loader).await
+    }
+
+    async fn exist(&self, file: &str) -> bool {
+        if self.inner.files.read().await.exist(file).await {
+            return true;
+        }
+        match &self.inner.disk {
+            Some(disk) => disk.exist(file).await,
+            None => false,
+        }
+    }
+
+    async fn peek(&self, file: &str) -> Option<Bytes> {
+        let files = self.inner.files.read().await;
+        if files.is_expired(file, Instant::now()) {
+            return None;
+        }
+        self.inner.data.get(file).map(|data| data.value().clone())
+    }
+
+    async fn remove(&self, file: &str) -> bool {
+        let removed = {
+            let mut files = self.inner.files.write().await;
+            files.remove(&self.inner.data, file).await.is_some()
+        };
+        match &self.inner.disk {
+            Some(disk) => disk.remove(file).await || removed,
+            None => removed,
+        }
+    }
+
+    async fn remove_prefix(&self, prefix: &str) -> usize {
+        let removed = {
+            let mut files = self.inner.files.write().await;
+            files.remove_prefix(&self.inner.data, prefix).await
+        };
+        match &self.inner.disk {
+            Some(disk) => removed + disk.remove_prefix(prefix).await,
+            None => removed,
+        }
+    }
+
+    async fn clear(&self) {
+        {
+            let mut files = self.inner.files.write().await;
+            files.clear(&self.inner.data).await;
+            self.inner.hits.clear();
+        }
+        if let Some(disk) = &self.inner.disk {
+            disk.clear().await;
+        }
+    }
+
+    async fn remove_expired(&self) -> usize {
+        let removed = {
+            let mut files = self.inner.files.write().await;
+            files.remove_expired(&self.inner.data).await
+        };
+        self.inner.metrics.record_expired(removed);
+        match &self.inner.disk {
+            Some(disk) => removed + disk.remove_expired().await,
+            None => removed,
+        }
+    }
+
+    async fn set_capacity(&self, max_size: usize) {
+        let evicted = {
+            let mut files = self.inner.files.write().await;
+            files.replay(&self.inner.hits);
+            files.set_capacity(&self.inner.data, max_size).await
+        };
+        self.release(evicted).await;
+    }
+
+    async fn len(&self) -> usize {
+        let files = self.inner.files.read().await;
+        files.len().await
+    }
+
+    async fn verify(&self) -> Result<(), CacheError> {
+        let files = self.inner.files.read().await;
+        files.verify(&self.inner.name, &self.inner.data).await
+    }
+
+    async fn entries(&self) -> Vec<SnapshotEntry> {
+        let mut files = self.inner.files.write().await;
+        files.replay(&self.inner.hits);
+        files.entries(&self.inner.data).await
+    }
+
+    async fn stats(&self) -> CacheStats {
+        let (disk_entries, disk_size, disk_expired) = match &self.inner.disk {
+            Some(disk) => (disk.len().await, disk.size().await, disk.expired()),
+            None => (0, 0, 0),
+        };
+        let mut stats = {
+            let files = self.inner.files.read().await;
+            CacheStats {
+                name: self.inner.name.clone(),
+                entries: files.len().await,
+                cur_size: files.cur_size,
+                max_size: files.max_size,
+                disk_entries,
+                disk_size,
+                ..Default::default()
+            }
+        };
+        self.inner.metrics.fill(&mut stats);
+        stats.expired += disk_expired;
+        stats
     }
 }
 
 #[inline]
 pub async fn get(file: &str, range: Option<Range<usize>>) -> Option<Bytes> {
-    let files = FILES.read().await;
-    files.get(file, range).await
+    match range {
+        Some(range) => FILES.get_range(file, range).await,
+        None => FILES.get(file).await,
+    }
 }
 
 #[inline]
 pub async fn exist(file: &str) -> bool {
-    let mut files = FILES.write().await;
-    files.exist(file).await
+    FILES.exist(file).await
 }
 
 #[inline]
-pub async fn set(session_id: &str, file: &str, data: Bytes) -> Result<(), anyhow::Error> {
-    let mut files = FILES.write().await;
-    if files.exist(file).await {
-        return Ok(());
-    }
-    files.set(session_id, file, data).await
+pub async fn set(session_id: &str, file: &str, data: Bytes) -> Result<(), CacheError> {
+    FILES.set(session_id, file, data).await
+}
+
+#[inline]
+pub async fn set_with_ttl(
+    session_id: &str,
+    file: &str,
+    data: Bytes,
+    ttl: Duration,
+) -> Result<(), CacheError> {
+    FILES.set_with_ttl(session_id, file, data, ttl).await
+}
+
+#[inline]
+pub async fn get_or_load<F, Fut>(file: &str, loader: F) -> Result<Bytes, CacheError>
+where
+    F: FnOnce() -> Fut + Send,
+    Fut: Future<Output = Result<Bytes, BoxError>> + Send + 'static,
+{
+    FILES.get_or_load(file, loader).await
+}
+
+#[inline]
+pub async fn remove(file: &str) -> bool {
+    FILES.remove(file).await
+}
+
+#[inline]
+pub async fn remove_prefix(prefix: &str) -> usize {
+    FILES.remove_prefix(prefix).await
+}
+
+#[inline]
+pub async fn clear() {
+    FILES.clear().await
 }
 
 #[inline]
-pub async fn len() -> (usize, usize) {
-    let files = FILES.read().await;
-    files.len().await
-}
-
-pub async fn download(session_id: &str, file: &str) -> Result<(), anyhow::Error> {
-    let data = bytes::Bytes::from("DATA.DATA.".repeat(10240));
-    if let Err(e) = set(session_id, file, data).await {
-        return Err(anyhow::anyhow!(
-            "set file {} to memory cache failed: {}",
-            file,
-            e
-        ));
+pub async fn len() -> usize {
+    FILES.len().await
+}
+
+pub async fn download(session_id: &str, file: &str) -> Result<(), CacheError> {
+    let loader = || async { Ok(bytes::Bytes::from("DATA.DATA.".repeat(10240))) };
+    if let Err(e) = get_or_load(file, loader).await {
+        log::error!(session_id; "download file {file} to memory cache failed: {e}");
+        return Err(e);
     };
     Ok(())
 }
 
-pub async fn check() -> Result<(), anyhow::Error> {
-    // let (file_len, data_len) = len().await;
-    // println!("file_len: {}, data_len: {}", file_len, data_len);
-    // let mut files = FILES.write().await;
-    // let mut keys = Vec::new();
-    // while let Some(file) = files.pop().await {
-    //     keys.push(file);
-    // }
-    // drop(files);
-    // for key in keys.iter() {
-    //     if get(key, None).await.is_none() {
-    //         println!("file: {} not exist", key);
-    //     }
-    // }
-    // println!("got files: {}", keys.len());
-    Ok(())
+pub async fn check() -> Result<(), CacheError> {
+    FILES.verify().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn set_corrupt_keeps_evicted() {
+        let data_map = RwHashMap::default();
+        let mut files = FileData::with_capacity(64, 0);
+        let data = Bytes::from("0123456789");
+        for file in ["a", "b"] {
+            let (evicted, ret) = files
+                .set("test", &data_map, "test", file, data.clone(), None)
+                .await;
+            ret.unwrap();
+            assert!(evicted.is_empty());
+        }
+        // bytes the index doesn't know about
+        files.cur_size += 40;
+
+        let (evicted, ret) = files
+            .set("test", &data_map, "test", "c", data.clone(), None)
+            .await;
+        assert!(matches!(ret, Err(CacheError::Corrupt { .. })));
+        let mut evicted = evicted.into_iter().map(|e| e.file).collect::<Vec<_>>();
+        evicted.sort();
+        assert_eq!(evicted, ["a", "b"]);
+        assert!(data_map.is_empty());
+        assert_eq!(files.cur_size, 40);
+    }
 }
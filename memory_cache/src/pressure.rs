@@ -0,0 +1,106 @@
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::{sysinfo, FileCache};
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Settings of the task shrinking a cache while the process uses too much
+/// memory.
+#[derive(Clone, Debug)]
+pub struct PressureConfig {
+    /// How often the process RSS is checked.
+    pub interval: Duration,
+    /// RSS above which the cache gives memory back.
+    pub high_water: usize,
+    /// The cache never shrinks below this size.
+    pub min_size: usize,
+}
+
+impl PressureConfig {
+    pub fn new(high_water: usize) -> PressureConfig {
+        PressureConfig {
+            interval: DEFAULT_INTERVAL,
+            high_water,
+            min_size: 0,
+        }
+    }
+
+    /// High-water mark as a fraction of the cgroup memory limit, or of the
+    /// node memory when there is no limit.
+    pub fn with_memory_fraction(fraction: f64) -> PressureConfig {
+        PressureConfig::new((cgroup::get_memory_limit() as f64 * fraction) as usize)
+    }
+
+    /// Capacity of the cache for the measured `rss`.
+    ///
+    /// Above the high-water mark the capacity drops by the excess, below it
+    /// grows back by half of the headroom, never beyond `max_size`, the
+    /// capacity the cache was created with.
+    pub fn next_capacity(&self, capacity: usize, max_size: usize, rss: usize) -> usize {
+        let min_size = self.min_size.min(max_size);
+        if rss > self.high_water {
+            capacity.saturating_sub(rss - self.high_water).max(min_size)
+        } else {
+            (capacity + (self.high_water - rss) / 2)
+                .min(max_size)
+                .max(min_size)
+        }
+    }
+}
+
+/// Resizes the cache returned by `upgrade` every interval, for as long as it
+/// returns the cache.
+pub(crate) fn spawn_watcher<C, F>(cfg: PressureConfig, upgrade: F) -> JoinHandle<()>
+where
+    C: FileCache,
+    F: Fn() -> Option<C> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let max_size = match upgrade() {
+            Some(cache) => cache.stats().await.max_size,
+            None => return,
+        };
+        let mut ticker = tokio::time::interval(cfg.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let Some(cache) = upgrade() else {
+                break;
+            };
+            let rss = tokio::task::spawn_blocking(sysinfo::get_process_memory_usage)
+                .await
+                .unwrap_or_default();
+            let capacity = cache.stats().await.max_size;
+            let next = cfg.next_capacity(capacity, max_size, rss);
+            if next != capacity {
+                log::info!(
+                    "File memory cache [{}] resized from {capacity} to {next} bytes, process rss {rss} bytes",
+                    cache.name()
+                );
+                cache.set_capacity(next).await;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_capacity() {
+        let mut cfg = PressureConfig::new(1000);
+        // shrink by the excess
+        assert_eq!(cfg.next_capacity(500, 500, 1200), 300);
+        assert_eq!(cfg.next_capacity(500, 500, 2000), 0);
+        // grow back slowly, up to the original size
+        assert_eq!(cfg.next_capacity(0, 500, 800), 100);
+        assert_eq!(cfg.next_capacity(450, 500, 800), 500);
+        assert_eq!(cfg.next_capacity(500, 500, 1000), 500);
+
+        cfg.min_size = 200;
+        assert_eq!(cfg.next_capacity(500, 500, 2000), 200);
+        assert_eq!(cfg.next_capacity(500, 100, 2000), 100);
+    }
+}
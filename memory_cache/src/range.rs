@@ -0,0 +1,383 @@
+//! Caching of byte ranges of files, e.g. the footer and a few column chunks
+//! of a large parquet file, instead of whole files.
+
+use bytes::{Bytes, BytesMut};
+use hashbrown::HashMap;
+use std::{collections::BTreeMap, ops::Range, sync::Arc};
+use tokio::sync::RwLock;
+
+use crate::{FileCache, Result};
+
+pub const DEFAULT_MAX_PIECE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Separates the file from the byte range in the keys of pieces, it can't
+/// appear in a file name.
+const RANGE_SEP: char = '\0';
+
+/// Pieces added to the index before it is first swept.
+const MIN_SWEEP: usize = 1024;
+
+/// Result of [`RangeCache::lookup`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RangeLookup {
+    /// Cached parts of the requested range as `(offset, data)`, ordered by
+    /// offset.
+    pub pieces: Vec<(usize, Bytes)>,
+    /// Parts of the requested range which are not cached, ordered.
+    pub missing: Vec<Range<usize>>,
+}
+
+impl RangeLookup {
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// Stores byte ranges of files as separate entries of a [`FileCache`].
+///
+/// The pieces of a file never overlap: overlapping and adjacent ranges are
+/// merged into a single piece as long as it stays below `max_piece_size`,
+/// otherwise only the uncovered parts of a new range are stored, split into
+/// pieces of at most `max_piece_size`. Pieces are evicted by the underlying
+/// cache like any other entry.
+pub struct RangeCache<C> {
+    inner: Arc<Inner<C>>,
+}
+
+struct Inner<C> {
+    cache: C,
+    max_piece_size: usize,
+    pieces: RwLock<Index>,
+}
+
+/// Pieces known to have been cached, including those the cache evicted since.
+#[derive(Default)]
+struct Index {
+    /// Start to end offset of the pieces of every file.
+    files: HashMap<String, BTreeMap<usize, usize>>,
+    /// Pieces added since the last sweep.
+    added: usize,
+    /// Pieces left by the last sweep.
+    kept: usize,
+}
+
+impl<C> Clone for RangeCache<C> {
+    fn clone(&self) -> Self {
+        RangeCache {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<C: FileCache> RangeCache<C> {
+    pub fn new(cache: C) -> RangeCache<C> {
+        RangeCache::with_max_piece_size(cache, DEFAULT_MAX_PIECE_SIZE)
+    }
+
+    pub fn with_max_piece_size(cache: C, max_piece_size: usize) -> RangeCache<C> {
+        RangeCache {
+            inner: Arc::new(Inner {
+                cache,
+                max_piece_size,
+                pieces: Default::default(),
+            }),
+        }
+    }
+
+    /// The cache holding the pieces.
+    pub fn cache(&self) -> &C {
+        &self.inner.cache
+    }
+
+    /// Caches `data` as the bytes of `file` starting at `offset`.
+    pub async fn set_range(
+        &self,
+        session_id: &str,
+        file: &str,
+        offset: usize,
+        data: Bytes,
+    ) -> Result<()> {
+        let range = offset..offset + data.len();
+        if range.is_empty() {
+            return Ok(());
+        }
+        let cache = &self.inner.cache;
+        let mut guard = self.inner.pieces.write().await;
+        let pieces = &mut *guard;
+        let index = pieces.files.entry(file.to_string()).or_default();
+
+        // cached pieces overlapping or touching the new range
+        let touching = index
+            .range(..=range.end)
+            .filter(|(_, &end)| end >= range.start)
+            .map(|(&start, &end)| start..end)
+            .collect::<Vec<_>>();
+        let mut neighbours = Vec::with_capacity(touching.len());
+        for piece in touching {
+            match cache.get(&piece_key(file, piece.clone())).await {
+                Some(data) => neighbours.push((piece.start, data)),
+                // evicted in the meantime
+                None => {
+                    index.remove(&piece.start);
+                }
+            }
+        }
+
+        let start = neighbours
+            .first()
+            .map_or(range.start, |(offset, _)| range.start.min(*offset));
+        let end = neighbours.last().map_or(range.end, |(offset, data)| {
+            range.end.max(offset + data.len())
+        });
+        if end - start <= self.inner.max_piece_size {
+            let mut buf = BytesMut::zeroed(end - start);
+            for (offset, data) in neighbours.iter() {
+                buf[offset - start..offset - start + data.len()].copy_from_slice(data);
+            }
+            buf[range.start - start..range.end - start].copy_from_slice(&data);
+            let merged = buf.freeze();
+            cache
+                .set(session_id, &piece_key(file, start..end), merged)
+                .await?;
+            for (offset, data) in neighbours.iter() {
+                let piece = *offset..offset + data.len();
+                if piece != (start..end) {
+                    cache.remove(&piece_key(file, piece)).await;
+                    index.remove(offset);
+                }
+            }
+            if index.insert(start, end).is_none() {
+                pieces.added += 1;
+            }
+        } else {
+            let covered = neighbours
+                .iter()
+                .map(|(offset, data)| *offset..offset + data.len())
+                .collect::<Vec<_>>();
+            let gaps = gaps(&covered, range.clone());
+            for gap in gaps {
+                for part in split(gap, self.inner.max_piece_size) {
+                    let piece = data.slice(part.start - range.start..part.end - range.start);
+                    cache
+                        .set(session_id, &piece_key(file, part.clone()), piece)
+                        .await?;
+                    if index.insert(part.start, part.end).is_none() {
+                        pieces.added += 1;
+                    }
+                }
+            }
+        }
+        if pieces.added > pieces.kept.max(MIN_SWEEP) {
+            self.sweep(pieces).await;
+        }
+        Ok(())
+    }
+
+    /// Returns the cached pieces of `range` of `file` along with the parts
+    /// which have to be fetched. A whole file cached under its own name
+    /// answers any range within it.
+    pub async fn lookup(&self, file: &str, range: Range<usize>) -> RangeLookup {
+        if range.is_empty() {
+            return RangeLookup::default();
+        }
+        let cache = &self.inner.cache;
+        if cache.exist(file).await {
+            if let Some(data) = cache.get_range(file, range.clone()).await {
+                return RangeLookup {
+                    pieces: vec![(range.start, data)],
+                    missing: Vec::new(),
+                };
+            }
+        }
+
+        let candidates = {
+            let pieces = self.inner.pieces.read().await;
+            let Some(index) = pieces.files.get(file) else {
+                return RangeLookup {
+                    pieces: Vec::new(),
+                    missing: vec![range],
+                };
+            };
+            index
+                .range(..range.end)
+                .filter(|(_, &end)| end > range.start)
+                .map(|(&start, &end)| start..end)
+                .collect::<Vec<_>>()
+        };
+        let mut found = Vec::new();
+        let mut evicted = Vec::new();
+        for piece in candidates {
+            let want = piece.start.max(range.start)..piece.end.min(range.end);
+            let key = piece_key(file, piece.clone());
+            match cache
+                .get_range(&key, want.start - piece.start..want.end - piece.start)
+                .await
+            {
+                Some(data) => found.push((want.start, data)),
+                None => evicted.push(piece.start),
+            }
+        }
+        if !evicted.is_empty() {
+            self.forget(file, &evicted).await;
+        }
+
+        let covered = found
+            .iter()
+            .map(|(offset, data)| *offset..offset + data.len())
+            .collect::<Vec<_>>();
+        RangeLookup {
+            missing: gaps(&covered, range),
+            pieces: found,
+        }
+    }
+
+    /// Returns `range` of `file` if it is fully cached, possibly assembled
+    /// from several pieces.
+    pub async fn get_range(&self, file: &str, range: Range<usize>) -> Option<Bytes> {
+        let len = range.len();
+        let lookup = self.lookup(file, range).await;
+        if !lookup.is_complete() {
+            return None;
+        }
+        match lookup.pieces.len() {
+            0 => Some(Bytes::new()),
+            1 => lookup.pieces.into_iter().next().map(|(_, data)| data),
+            _ => {
+                let mut buf = BytesMut::with_capacity(len);
+                for (_, data) in lookup.pieces {
+                    buf.extend_from_slice(&data);
+                }
+                Some(buf.freeze())
+            }
+        }
+    }
+
+    /// Parts of `range` of `file` which are not cached.
+    pub async fn missing(&self, file: &str, range: Range<usize>) -> Vec<Range<usize>> {
+        self.lookup(file, range).await.missing
+    }
+
+    /// Drops every cached piece of `file`, returns how many were removed.
+    pub async fn remove(&self, file: &str) -> usize {
+        let Some(index) = self.inner.pieces.write().await.files.remove(file) else {
+            return 0;
+        };
+        for (&start, &end) in index.iter() {
+            self.inner.cache.remove(&piece_key(file, start..end)).await;
+        }
+        index.len()
+    }
+
+    async fn forget(&self, file: &str, starts: &[usize]) {
+        let mut pieces = self.inner.pieces.write().await;
+        if let Some(index) = pieces.files.get_mut(file) {
+            for start in starts {
+                index.remove(start);
+            }
+            if index.is_empty() {
+                pieces.files.remove(file);
+            }
+        }
+    }
+
+    /// Drops the pieces evicted by the cache from the index. It runs once as
+    /// many pieces were added as the previous sweep left, so the index of
+    /// files which are never looked up again stays within twice the pieces
+    /// actually cached.
+    async fn sweep(&self, pieces: &mut Index) {
+        let mut kept = 0;
+        for (file, index) in pieces.files.iter_mut() {
+            let mut evicted = Vec::new();
+            for (&start, &end) in index.iter() {
+                if !self.inner.cache.exist(&piece_key(file, start..end)).await {
+                    evicted.push(start);
+                }
+            }
+            for start in evicted {
+                index.remove(&start);
+            }
+            kept += index.len();
+        }
+        pieces.files.retain(|_, index| !index.is_empty());
+        pieces.added = 0;
+        pieces.kept = kept;
+    }
+}
+
+fn piece_key(file: &str, range: Range<usize>) -> String {
+    format!("{file}{RANGE_SEP}{}-{}", range.start, range.end)
+}
+
+/// Parts of `range` not covered by the ordered, disjoint ranges `covered`.
+fn gaps(covered: &[Range<usize>], range: Range<usize>) -> Vec<Range<usize>> {
+    let mut gaps = Vec::new();
+    let mut pos = range.start;
+    for piece in covered {
+        if piece.start > pos {
+            gaps.push(pos..piece.start.min(range.end));
+        }
+        pos = pos.max(piece.end);
+        if pos >= range.end {
+            break;
+        }
+    }
+    if pos < range.end {
+        gaps.push(pos..range.end);
+    }
+    gaps
+}
+
+/// Cuts `range` into consecutive ranges of at most `max_len`.
+fn split(range: Range<usize>, max_len: usize) -> impl Iterator<Item = Range<usize>> {
+    let max_len = max_len.max(1);
+    range
+        .clone()
+        .step_by(max_len)
+        .map(move |start| start..range.end.min(start + max_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_v1;
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_gaps() {
+        assert_eq!(gaps(&[], 0..10), vec![0..10]);
+        assert_eq!(gaps(&[0..10], 0..10), Vec::<Range<usize>>::new());
+        assert_eq!(gaps(&[2..4, 6..8], 0..10), vec![0..2, 4..6, 8..10]);
+        assert_eq!(gaps(&[0..4, 4..8], 2..10), vec![8..10]);
+        assert_eq!(gaps(&[5..20], 0..10), vec![0..5]);
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_split() {
+        assert_eq!(split(0..10, 4).collect::<Vec<_>>(), vec![0..4, 4..8, 8..10]);
+        assert_eq!(split(2..10, 4).collect::<Vec<_>>(), vec![2..6, 6..10]);
+        assert_eq!(split(0..10, 10).collect::<Vec<_>>(), vec![0..10]);
+    }
+
+    #[tokio::test]
+    async fn test_sweep_evicted() {
+        let cache = RangeCache::new(memory_v1::FileCache::with_capacity("test", 4096, 1024));
+        let data = Bytes::from(vec![0u8; 1024]);
+        for i in 0..3 * MIN_SWEEP {
+            cache
+                .set_range("test", &format!("f{i}"), 0, data.clone())
+                .await
+                .unwrap();
+            let pieces = cache.inner.pieces.read().await;
+            let len = pieces.files.values().map(BTreeMap::len).sum::<usize>();
+            assert!(len <= MIN_SWEEP + 4, "{len} pieces indexed");
+        }
+        // only files still cached are left after a sweep
+        let mut pieces = cache.inner.pieces.write().await;
+        cache.sweep(&mut pieces).await;
+        assert_eq!(pieces.files.len(), cache.cache().len().await);
+        assert!(pieces
+            .files
+            .contains_key(&format!("f{}", 3 * MIN_SWEEP - 1)));
+    }
+}
@@ -0,0 +1,220 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use hash::Sum64;
+use std::{future::Future, ops::Range, sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+
+use crate::{
+    config::CacheConfig,
+    errors::BoxError,
+    pressure::{self, PressureConfig},
+    snapshot::SnapshotEntry,
+    stats::CacheStats,
+    ttl, FileCache, Result,
+};
+
+pub const DEFAULT_SHARDS: usize = 16;
+
+/// A cache split into independent shards of the backend `C`.
+///
+/// Files are routed to a shard by the FNV-1a hash of their name. Every shard
+/// gets an equal part of the memory and disk budget and runs its own
+/// eviction, so writers to different shards never wait for each other.
+pub struct ShardedCache<C> {
+    inner: Arc<Inner<C>>,
+}
+
+struct Inner<C> {
+    name: String,
+    shards: Vec<C>,
+}
+
+impl<C> Clone for ShardedCache<C> {
+    fn clone(&self) -> Self {
+        ShardedCache {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<C: FileCache> ShardedCache<C> {
+    /// Builds `shards` backends with `build`, e.g.
+    /// `ShardedCache::with_config(cfg, 16, memory_v1::FileCache::with_config)`.
+    pub fn with_config<F>(cfg: CacheConfig, shards: usize, build: F) -> ShardedCache<C>
+    where
+        F: Fn(CacheConfig) -> C,
+    {
+        let num = shards.max(1);
+        let shards = (0..num)
+            .map(|i| {
+                let mut shard = cfg.clone();
+                shard.name = format!("{}-{i}", cfg.name);
+                shard.max_size = cfg.max_size / num;
+                shard.release_size = cfg.release_size / num;
+                if let Some(disk) = shard.disk.as_mut() {
+                    disk.dir = disk.dir.join(format!("shard-{i}"));
+                    disk.max_size /= num;
+                }
+                build(shard)
+            })
+            .collect();
+        ShardedCache {
+            inner: Arc::new(Inner {
+                name: cfg.name,
+                shards,
+            }),
+        }
+    }
+
+    pub fn shards(&self) -> &[C] {
+        &self.inner.shards
+    }
+
+    /// Spawns a task removing expired entries of every shard each
+    /// `interval`, it stops once every handle of the cache was dropped.
+    pub fn start_sweeper(&self, interval: Duration) -> JoinHandle<()> {
+        let inner = Arc::downgrade(&self.inner);
+        ttl::spawn_sweeper(interval, move || {
+            inner.upgrade().map(|inner| ShardedCache { inner })
+        })
+    }
+
+    /// Spawns a task resizing the shards with the memory usage of the
+    /// process, see [`PressureConfig`].
+    pub fn start_memory_watcher(&self, cfg: PressureConfig) -> JoinHandle<()> {
+        let inner = Arc::downgrade(&self.inner);
+        pressure::spawn_watcher(cfg, move || {
+            inner.upgrade().map(|inner| ShardedCache { inner })
+        })
+    }
+
+    fn shard(&self, file: &str) -> &C {
+        let hash = hash::fnv::new().sum64(file);
+        &self.inner.shards[(hash % self.inner.shards.len() as u64) as usize]
+    }
+}
+
+#[async_trait]
+impl<C: FileCache> FileCache for ShardedCache<C> {
+    fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    async fn get(&self, file: &str) -> Option<Bytes> {
+        self.shard(file).get(file).await
+    }
+
+    async fn get_range(&self, file: &str, range: Range<usize>) -> Option<Bytes> {
+        self.shard(file).get_range(file, range).await
+    }
+
+    async fn set(&self, session_id: &str, file: &str, data: Bytes) -> Result<()> {
+        self.shard(file).set(session_id, file, data).await
+    }
+
+    async fn set_with_ttl(
+        &self,
+        session_id: &str,
+        file: &str,
+        data: Bytes,
+        ttl: Duration,
+    ) -> Result<()> {
+        self.shard(file)
+            .set_with_ttl(session_id, file, data, ttl)
+            .await
+    }
+
+    async fn get_or_load<F, Fut>(&self, file: &str, loader: F) -> Result<Bytes>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = std::result::Result<Bytes, BoxError>> + Send + 'static,
+    {
+        self.shard(file).get_or_load(file, loader).await
+    }
+
+    async fn exist(&self, file: &str) -> bool {
+        self.shard(file).exist(file).await
+    }
+
+    async fn peek(&self, file: &str) -> Option<Bytes> {
+        self.shard(file).peek(file).await
+    }
+
+    async fn remove(&self, file: &str) -> bool {
+        self.shard(file).remove(file).await
+    }
+
+    async fn remove_prefix(&self, prefix: &str) -> usize {
+        let mut removed = 0;
+        for shard in self.inner.shards.iter() {
+            removed += shard.remove_prefix(prefix).await;
+        }
+        removed
+    }
+
+    async fn clear(&self) {
+        for shard in self.inner.shards.iter() {
+            shard.clear().await;
+        }
+    }
+
+    async fn remove_expired(&self) -> usize {
+        let mut removed = 0;
+        for shard in self.inner.shards.iter() {
+            removed += shard.remove_expired().await;
+        }
+        removed
+    }
+
+    /// Every shard gets an equal part of `max_size`.
+    async fn set_capacity(&self, max_size: usize) {
+        let num = self.inner.shards.len();
+        for shard in self.inner.shards.iter() {
+            shard.set_capacity(max_size / num).await;
+        }
+    }
+
+    async fn len(&self) -> usize {
+        let mut len = 0;
+        for shard in self.inner.shards.iter() {
+            len += shard.len().await;
+        }
+        len
+    }
+
+    async fn verify(&self) -> Result<()> {
+        for shard in self.inner.shards.iter() {
+            shard.verify().await?;
+        }
+        Ok(())
+    }
+
+    /// Shards are interleaved, so the order is only approximate across
+    /// shards.
+    async fn entries(&self) -> Vec<SnapshotEntry> {
+        let mut shards = Vec::with_capacity(self.inner.shards.len());
+        for shard in self.inner.shards.iter() {
+            shards.push(shard.entries().await.into_iter());
+        }
+        let mut entries = Vec::new();
+        loop {
+            let len = entries.len();
+            entries.extend(shards.iter_mut().filter_map(|shard| shard.next()));
+            if entries.len() == len {
+                break;
+            }
+        }
+        entries
+    }
+
+    async fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats {
+            name: self.inner.name.clone(),
+            ..Default::default()
+        };
+        for shard in self.inner.shards.iter() {
+            stats.merge(&shard.stats().await);
+        }
+        stats
+    }
+}
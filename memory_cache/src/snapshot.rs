@@ -0,0 +1,234 @@
+use bytes::Bytes;
+use snafu::ResultExt;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    errors::{CacheError, IoSnafu, SnapshotSnafu},
+    FileCache, Result,
+};
+
+/// File magic followed by the format version.
+const MAGIC: &[u8; 8] = b"MCSNAP\x00\x01";
+const SESSION_ID: &str = "snapshot";
+
+/// A cached file as written into a snapshot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    pub file: String,
+    pub data: Bytes,
+    /// Time left before the entry expires.
+    pub ttl: Option<Duration>,
+}
+
+/// Writes `entries` to `path`, hottest first.
+///
+/// The layout is the header `MAGIC | count: u64 | crc32: u32` followed by
+/// `count` records of
+/// `file_len: u32 | data_len: u64 | expires_at: u64 | file | data | crc32: u32`,
+/// all integers little endian. `expires_at` is in unix milliseconds, `0` for
+/// entries which never expire, and every crc32 covers the bytes of its header
+/// or record before it.
+///
+/// The snapshot is written next to `path` and renamed into place once
+/// complete, so a crash never leaves a truncated snapshot behind.
+pub(crate) async fn write(path: &Path, entries: Vec<SnapshotEntry>) -> Result<usize> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let tmp = path.with_extension("tmp");
+        write_file(&tmp, &entries).context(IoSnafu { path: &tmp })?;
+        std::fs::rename(&tmp, &path).context(IoSnafu { path })?;
+        Ok(entries.len())
+    })
+    .await
+    .expect("snapshot writer panicked")
+}
+
+fn write_file(path: &Path, entries: &[SnapshotEntry]) -> std::io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+    w.write_all(&header)?;
+    w.write_all(&crc32fast::hash(&header).to_le_bytes())?;
+
+    let now = SystemTime::now();
+    for entry in entries {
+        let expires_at = entry
+            .ttl
+            .map(|ttl| unix_millis(now + ttl).max(1))
+            .unwrap_or(0);
+        let mut head = Vec::with_capacity(20);
+        head.extend_from_slice(&(entry.file.len() as u32).to_le_bytes());
+        head.extend_from_slice(&(entry.data.len() as u64).to_le_bytes());
+        head.extend_from_slice(&expires_at.to_le_bytes());
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&head);
+        crc.update(entry.file.as_bytes());
+        crc.update(&entry.data);
+        w.write_all(&head)?;
+        w.write_all(entry.file.as_bytes())?;
+        w.write_all(&entry.data)?;
+        w.write_all(&crc.finalize().to_le_bytes())?;
+    }
+    w.into_inner()?.sync_all()
+}
+
+/// Reads the entries of a snapshot, hottest first.
+///
+/// Reading stops at the first entry which would take the total size
+/// (`file.len() + data.len()` as in the caches) over `max_bytes`, entries
+/// which expired in the meantime are skipped.
+pub(crate) async fn read(path: &Path, max_bytes: Option<usize>) -> Result<Vec<SnapshotEntry>> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || read_file(&path, max_bytes.unwrap_or(usize::MAX)))
+        .await
+        .expect("snapshot reader panicked")
+}
+
+fn read_file(path: &Path, max_bytes: usize) -> Result<Vec<SnapshotEntry>> {
+    let file = File::open(path).context(IoSnafu { path })?;
+    let file_size = file.metadata().context(IoSnafu { path })?.len();
+    let mut r = BufReader::new(file);
+    let invalid = |e: std::io::Error| -> CacheError {
+        if e.kind() == ErrorKind::UnexpectedEof {
+            SnapshotSnafu {
+                path,
+                reason: "file is truncated",
+            }
+            .build()
+        } else {
+            CacheError::Io {
+                path: path.to_path_buf(),
+                source: e,
+            }
+        }
+    };
+
+    let mut header = [0u8; 16];
+    r.read_exact(&mut header).map_err(invalid)?;
+    if &header[..8] != MAGIC {
+        return SnapshotSnafu {
+            path,
+            reason: "not a cache snapshot",
+        }
+        .fail();
+    }
+    if read_u32(&mut r).map_err(invalid)? != crc32fast::hash(&header) {
+        return SnapshotSnafu {
+            path,
+            reason: "header checksum mismatch",
+        }
+        .fail();
+    }
+    let count = u64::from_le_bytes(header[8..].try_into().unwrap());
+
+    let now = unix_millis(SystemTime::now());
+    let mut entries = Vec::new();
+    let mut total: usize = 0;
+    // bytes read so far, the header and its crc
+    let mut pos: u64 = 20;
+    for i in 0..count {
+        let mut head = [0u8; 20];
+        r.read_exact(&mut head).map_err(invalid)?;
+        let file_len = u32::from_le_bytes(head[..4].try_into().unwrap());
+        let data_len = u64::from_le_bytes(head[4..12].try_into().unwrap());
+        let expires_at = u64::from_le_bytes(head[12..].try_into().unwrap());
+        // the lengths aren't checked by the crc yet, they must not make us
+        // allocate more than the file holds
+        let record_len = data_len
+            .checked_add(u64::from(file_len) + 24)
+            .filter(|&len| len <= file_size.saturating_sub(pos));
+        let Some(record_len) = record_len else {
+            return SnapshotSnafu {
+                path,
+                reason: format!("file is truncated or entry {i} is corrupt"),
+            }
+            .fail();
+        };
+        pos += record_len;
+        let (file_len, data_len) = (file_len as usize, data_len as usize);
+        if total.saturating_add(file_len + data_len) > max_bytes {
+            break;
+        }
+        let mut file = vec![0u8; file_len];
+        r.read_exact(&mut file).map_err(invalid)?;
+        let mut data = vec![0u8; data_len];
+        r.read_exact(&mut data).map_err(invalid)?;
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&head);
+        crc.update(&file);
+        crc.update(&data);
+        if read_u32(&mut r).map_err(invalid)? != crc.finalize() {
+            return SnapshotSnafu {
+                path,
+                reason: format!("checksum mismatch in entry {i}"),
+            }
+            .fail();
+        }
+        let Ok(file) = String::from_utf8(file) else {
+            return SnapshotSnafu {
+                path,
+                reason: format!("file name of entry {i} is not utf-8"),
+            }
+            .fail();
+        };
+        let ttl = match expires_at {
+            0 => None,
+            expires_at if expires_at <= now => continue,
+            expires_at => Some(Duration::from_millis(expires_at - now)),
+        };
+        total += file_len + data_len;
+        entries.push(SnapshotEntry {
+            file,
+            data: Bytes::from(data),
+            ttl,
+        });
+    }
+    Ok(entries)
+}
+
+/// Loads a snapshot into `cache`, coldest entries first so the hottest ones
+/// end up most recently used.
+pub(crate) async fn restore<C: FileCache + ?Sized>(
+    cache: &C,
+    path: &Path,
+    max_bytes: Option<usize>,
+) -> Result<usize> {
+    let entries = read(path, max_bytes).await?;
+    let mut restored = 0;
+    for entry in entries.into_iter().rev() {
+        let ret = match entry.ttl {
+            Some(ttl) => {
+                cache
+                    .set_with_ttl(SESSION_ID, &entry.file, entry.data, ttl)
+                    .await
+            }
+            None => cache.set(SESSION_ID, &entry.file, entry.data).await,
+        };
+        match ret {
+            Ok(()) => restored += 1,
+            Err(e) => log::warn!(
+                "File memory cache [{}] failed to restore {}: {e}",
+                cache.name(),
+                entry.file
+            ),
+        }
+    }
+    Ok(restored)
+}
+
+fn read_u32(r: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn unix_millis(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,239 @@
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Point-in-time view of a cache instance.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub name: String,
+    pub entries: usize,
+    pub cur_size: usize,
+    pub max_size: usize,
+    pub disk_entries: usize,
+    pub disk_size: usize,
+    /// Whole file lookups served from memory or disk.
+    pub hits: u64,
+    /// Range lookups served from memory or disk.
+    pub range_hits: u64,
+    /// Hits, whole file or range, served by the disk tier.
+    pub disk_hits: u64,
+    /// Lookups of files which were not cached.
+    pub misses: u64,
+    pub inserts: u64,
+    /// Entries released from memory because the cache was full.
+    pub evictions: u64,
+    pub evicted_bytes: u64,
+    /// Entries dropped because their TTL passed.
+    pub expired: u64,
+}
+
+impl CacheStats {
+    pub fn hit_ratio(&self) -> f64 {
+        let hits = self.hits + self.range_hits;
+        let total = hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        hits as f64 / total as f64
+    }
+
+    /// Adds the sizes and counters of `other`, used to sum up the shards of
+    /// a cache.
+    pub fn merge(&mut self, other: &CacheStats) {
+        self.entries += other.entries;
+        self.cur_size += other.cur_size;
+        self.max_size += other.max_size;
+        self.disk_entries += other.disk_entries;
+        self.disk_size += other.disk_size;
+        self.hits += other.hits;
+        self.range_hits += other.range_hits;
+        self.disk_hits += other.disk_hits;
+        self.misses += other.misses;
+        self.inserts += other.inserts;
+        self.evictions += other.evictions;
+        self.evicted_bytes += other.evicted_bytes;
+        self.expired += other.expired;
+    }
+}
+
+/// Counters shared by every handle of a cache instance.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    hits: AtomicU64,
+    range_hits: AtomicU64,
+    disk_hits: AtomicU64,
+    misses: AtomicU64,
+    inserts: AtomicU64,
+    evictions: AtomicU64,
+    evicted_bytes: AtomicU64,
+    expired: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_get(&self, found: bool, range: bool) {
+        let counter = match (found, range) {
+            (true, false) => &self.hits,
+            (true, true) => &self.range_hits,
+            (false, _) => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_disk_get(&self, found: bool, range: bool) {
+        self.record_get(found, range);
+        if found {
+            self.disk_hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_insert(&self) {
+        self.inserts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_evictions(&self, entries: usize, bytes: usize) {
+        self.evictions.fetch_add(entries as u64, Ordering::Relaxed);
+        self.evicted_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_expired(&self, entries: usize) {
+        self.expired.fetch_add(entries as u64, Ordering::Relaxed);
+    }
+
+    /// Copies the counters into `stats`.
+    pub fn fill(&self, stats: &mut CacheStats) {
+        stats.hits = self.hits.load(Ordering::Relaxed);
+        stats.range_hits = self.range_hits.load(Ordering::Relaxed);
+        stats.disk_hits = self.disk_hits.load(Ordering::Relaxed);
+        stats.misses = self.misses.load(Ordering::Relaxed);
+        stats.inserts = self.inserts.load(Ordering::Relaxed);
+        stats.evictions = self.evictions.load(Ordering::Relaxed);
+        stats.evicted_bytes = self.evicted_bytes.load(Ordering::Relaxed);
+        stats.expired = self.expired.load(Ordering::Relaxed);
+    }
+}
+
+/// Encodes the stats of several caches in the Prometheus text exposition
+/// format, every series is labelled with the cache name.
+pub fn encode_prometheus(stats: &[CacheStats]) -> String {
+    let mut out = String::new();
+    let mut counter = |name, help, value: fn(&CacheStats) -> u64| {
+        write_metric(&mut out, name, "counter", help, stats, value)
+    };
+    counter(
+        "hits_total",
+        "Whole file lookups served by the cache.",
+        |s| s.hits,
+    );
+    counter(
+        "range_hits_total",
+        "Range lookups served by the cache.",
+        |s| s.range_hits,
+    );
+    counter("disk_hits_total", "Lookups served by the disk tier.", |s| {
+        s.disk_hits
+    });
+    counter(
+        "misses_total",
+        "Lookups of files which were not cached.",
+        |s| s.misses,
+    );
+    counter("inserts_total", "Files written into the cache.", |s| {
+        s.inserts
+    });
+    counter(
+        "evictions_total",
+        "Entries released because the cache was full.",
+        |s| s.evictions,
+    );
+    counter(
+        "evicted_bytes_total",
+        "Bytes released because the cache was full.",
+        |s| s.evicted_bytes,
+    );
+    counter(
+        "expired_total",
+        "Entries dropped because their TTL passed.",
+        |s| s.expired,
+    );
+    let mut gauge = |name, help, value: fn(&CacheStats) -> u64| {
+        write_metric(&mut out, name, "gauge", help, stats, value)
+    };
+    gauge("entries", "Entries held in memory.", |s| s.entries as u64);
+    gauge("bytes", "Bytes held in memory.", |s| s.cur_size as u64);
+    gauge("max_bytes", "Memory capacity in bytes.", |s| {
+        s.max_size as u64
+    });
+    gauge("disk_entries", "Entries held by the disk tier.", |s| {
+        s.disk_entries as u64
+    });
+    gauge("disk_bytes", "Bytes held by the disk tier.", |s| {
+        s.disk_size as u64
+    });
+    out
+}
+
+fn write_metric(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    stats: &[CacheStats],
+    value: fn(&CacheStats) -> u64,
+) {
+    let _ = writeln!(out, "# HELP memory_cache_{name} {help}");
+    let _ = writeln!(out, "# TYPE memory_cache_{name} {kind}");
+    for s in stats {
+        let _ = writeln!(
+            out,
+            "memory_cache_{name}{{cache=\"{}\"}} {}",
+            escape_label(&s.name),
+            value(s)
+        );
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit_ratio() {
+        let mut stats = CacheStats::default();
+        assert_eq!(stats.hit_ratio(), 0.0);
+        stats.hits = 2;
+        stats.range_hits = 1;
+        stats.misses = 1;
+        assert_eq!(stats.hit_ratio(), 0.75);
+    }
+
+    #[test]
+    fn test_encode_prometheus() {
+        let stats = [
+            CacheStats {
+                name: "parquet".to_string(),
+                hits: 3,
+                cur_size: 1024,
+                ..Default::default()
+            },
+            CacheStats {
+                name: "in\"dex".to_string(),
+                misses: 1,
+                ..Default::default()
+            },
+        ];
+        let text = encode_prometheus(&stats);
+        assert!(text.contains("# TYPE memory_cache_hits_total counter\n"));
+        assert!(text.contains("memory_cache_hits_total{cache=\"parquet\"} 3\n"));
+        assert!(text.contains("memory_cache_misses_total{cache=\"in\\\"dex\"} 1\n"));
+        assert!(text.contains("memory_cache_bytes{cache=\"parquet\"} 1024\n"));
+    }
+}
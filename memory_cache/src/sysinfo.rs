@@ -0,0 +1,19 @@
+//! Memory usage lookup of `tprun::sysinfo::mem`, the limits come from the
+//! shared `cgroup` crate.
+
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, RefreshKind};
+
+// Get process memory usage in bytes
+pub fn get_process_memory_usage() -> usize {
+    let Ok(pid) = sysinfo::get_current_pid() else {
+        return 0;
+    };
+    let mut system = sysinfo::System::new_with_specifics(
+        RefreshKind::nothing().with_processes(ProcessRefreshKind::nothing().with_memory()),
+    );
+    system.refresh_processes(ProcessesToUpdate::Some(&[pid]), false);
+    system
+        .process(pid)
+        .map(|p| p.memory() as usize)
+        .unwrap_or_default()
+}
@@ -0,0 +1,26 @@
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::FileCache;
+
+/// Runs [`FileCache::remove_expired`] every `interval` for as long as
+/// `upgrade` still returns the cache.
+pub(crate) fn spawn_sweeper<C, F>(interval: Duration, upgrade: F) -> JoinHandle<()>
+where
+    C: FileCache,
+    F: Fn() -> Option<C> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // the first tick completes immediately
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let Some(cache) = upgrade() else {
+                break;
+            };
+            cache.remove_expired().await;
+        }
+    })
+}
@@ -0,0 +1,75 @@
+use hashbrown::HashMap;
+use tokio::time::Instant;
+
+use crate::{errors::CorruptSnafu, eviction::EvictionPolicy, Result};
+
+/// Bookkeeping of a backend as seen by [`check`].
+pub(crate) struct Books<'a> {
+    pub name: &'a str,
+    pub max_size: usize,
+    pub cur_size: usize,
+    pub index: &'a dyn EvictionPolicy,
+    pub expires: &'a HashMap<String, Instant>,
+    /// Number of files in the data map.
+    pub data_len: usize,
+}
+
+/// Checks the invariants shared by the backends:
+///
+/// - `cur_size` never exceeds `max_size`,
+/// - the eviction index and the data map hold the same files,
+/// - `cur_size` is the sum of `file.len() + data.len()` over all files,
+/// - only indexed files have an expiry time.
+///
+/// `data_size` returns the size of the cached data of a file, `None` if the
+/// data map lacks it.
+pub(crate) fn check<F>(books: Books<'_>, data_size: F) -> Result<()>
+where
+    F: Fn(&str) -> Option<usize>,
+{
+    let corrupt = |reason: String| {
+        CorruptSnafu {
+            name: books.name,
+            reason,
+        }
+        .fail()
+    };
+    if books.cur_size > books.max_size {
+        return corrupt(format!(
+            "size {} exceeds the limit {}",
+            books.cur_size, books.max_size
+        ));
+    }
+    let keys = books.index.keys();
+    if keys.len() != books.index.len() {
+        return corrupt(format!(
+            "eviction index lists {} of its {} files",
+            keys.len(),
+            books.index.len()
+        ));
+    }
+    if keys.len() != books.data_len {
+        return corrupt(format!(
+            "eviction index has {} files but the data map {}",
+            keys.len(),
+            books.data_len
+        ));
+    }
+    let mut size = 0;
+    for key in keys.iter() {
+        let Some(data_size) = data_size(key) else {
+            return corrupt(format!("indexed file {key} has no data"));
+        };
+        size += key.len() + data_size;
+    }
+    if size != books.cur_size {
+        return corrupt(format!(
+            "size is {} but the files take {size} bytes",
+            books.cur_size
+        ));
+    }
+    if let Some(key) = books.expires.keys().find(|key| !books.index.contains(key)) {
+        return corrupt(format!("expiry time of unknown file {key}"));
+    }
+    Ok(())
+}
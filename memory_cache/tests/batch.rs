@@ -0,0 +1,182 @@
+use arrow::{
+    array::{Array, Float64Array, Int32Array, StringArray},
+    datatypes::{DataType, Field, Schema},
+    ipc::writer::StreamWriter,
+    record_batch::RecordBatch,
+};
+use bytes::Bytes;
+use memory_cache::{memory_v1, BatchCache, CacheError, FileCache};
+use std::sync::Arc;
+
+/// An IPC stream of `batches` batches with 10 rows each, `id` counts the
+/// rows from 0.
+fn ipc_stream(batches: usize) -> Bytes {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("score", DataType::Float64, false),
+    ]));
+    let mut buf = Vec::new();
+    let mut writer = StreamWriter::try_new(&mut buf, &schema).unwrap();
+    for b in 0..batches {
+        let ids = (b * 10..(b + 1) * 10).map(|i| i as i32).collect::<Vec<_>>();
+        let names = ids.iter().map(|i| format!("name-{i}")).collect::<Vec<_>>();
+        let scores = ids.iter().map(|i| *i as f64 / 2.0).collect::<Vec<_>>();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(ids)),
+                Arc::new(StringArray::from(names)),
+                Arc::new(Float64Array::from(scores)),
+            ],
+        )
+        .unwrap();
+        writer.write(&batch).unwrap();
+    }
+    writer.finish().unwrap();
+    drop(writer);
+    Bytes::from(buf)
+}
+
+fn ids(batches: &memory_cache::Batches) -> Vec<i32> {
+    let idx = batches.schema.index_of("id").unwrap();
+    batches
+        .batches
+        .iter()
+        .flat_map(|b| {
+            let col = b.column(idx).as_any().downcast_ref::<Int32Array>().unwrap();
+            col.values().to_vec()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn project_and_slice() {
+    let cache = BatchCache::new("test", 1024 * 1024);
+    let batches = cache.set("a.arrow", ipc_stream(3), None).await.unwrap();
+    assert_eq!(batches.num_rows(), 30);
+    assert_eq!(batches.batches.len(), 3);
+
+    // columns are served from the whole file without decoding again
+    let projected = cache.get("a.arrow", Some(&[2, 0]), None).await.unwrap();
+    let names = projected
+        .schema
+        .fields()
+        .iter()
+        .map(|f| f.name().as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["score", "id"]);
+    assert_eq!(projected.num_rows(), 30);
+    assert_eq!(cache.len().await, 1);
+
+    // row ranges span batches
+    let rows = cache.get("a.arrow", None, Some(5..25)).await.unwrap();
+    assert_eq!(rows.batches.len(), 3);
+    assert_eq!(ids(&rows), (5..25).collect::<Vec<_>>());
+    let rows = cache
+        .get("a.arrow", Some(&[0]), Some(10..20))
+        .await
+        .unwrap();
+    assert_eq!(rows.batches.len(), 1);
+    assert_eq!(ids(&rows), (10..20).collect::<Vec<_>>());
+    assert!(cache.get("a.arrow", None, Some(20..31)).await.is_none());
+    assert!(cache.get("a.arrow", Some(&[3]), None).await.is_none());
+    assert!(cache.get("b.arrow", None, None).await.is_none());
+
+    let stats = cache.stats().await;
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.range_hits, 2);
+    assert_eq!(stats.misses, 3);
+}
+
+#[tokio::test]
+async fn cache_projection() {
+    let cache = BatchCache::new("test", 1024 * 1024);
+    let projected = cache
+        .set("a.arrow", ipc_stream(2), Some(&[0]))
+        .await
+        .unwrap();
+    assert_eq!(projected.schema.fields().len(), 1);
+    assert!(cache.get("a.arrow", Some(&[0]), None).await.is_some());
+    // the whole file or other columns were never decoded
+    assert!(cache.get("a.arrow", None, None).await.is_none());
+    assert!(cache.get("a.arrow", Some(&[1]), None).await.is_none());
+
+    cache.set("a.arrow", ipc_stream(2), None).await.unwrap();
+    let stats = cache.stats().await;
+    assert_eq!(stats.entries, 2);
+    assert_eq!(cache.remove("a.arrow").await, 2);
+    assert!(cache.is_empty().await);
+    assert_eq!(cache.stats().await.cur_size, 0);
+}
+
+#[tokio::test]
+async fn memory_size_accounting() {
+    let data = ipc_stream(2);
+    let probe = BatchCache::new("probe", usize::MAX);
+    let size = "f0".len()
+        + probe
+            .set("f0", data.clone(), None)
+            .await
+            .unwrap()
+            .memory_size();
+    assert_eq!(probe.stats().await.cur_size, size);
+
+    let cache = BatchCache::new("test", 3 * size);
+    for i in 0..5 {
+        cache
+            .set(&format!("f{i}"), data.clone(), None)
+            .await
+            .unwrap();
+    }
+    let stats = cache.stats().await;
+    assert_eq!(stats.entries, 3);
+    assert_eq!(stats.cur_size, 3 * size);
+    assert_eq!(stats.evictions, 2);
+    assert!(cache.get("f0", None, None).await.is_none());
+    assert!(cache.get("f4", None, None).await.is_some());
+
+    let err = BatchCache::new("small", size / 2)
+        .set("f0", data, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, CacheError::EntryTooLarge { .. }), "{err}");
+}
+
+#[tokio::test]
+async fn get_or_decode() {
+    let source = memory_v1::FileCache::new("ipc");
+    source.set("test", "a.arrow", ipc_stream(3)).await.unwrap();
+    source
+        .set("test", "bad.arrow", Bytes::from("not arrow"))
+        .await
+        .unwrap();
+    let cache = BatchCache::new("test", 1024 * 1024);
+
+    let rows = cache
+        .get_or_decode(&source, "a.arrow", Some(&[0]), Some(0..5))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(ids(&rows), (0..5).collect::<Vec<_>>());
+    // the second read is served by the decoded batches
+    source.remove("a.arrow").await;
+    let rows = cache
+        .get_or_decode(&source, "a.arrow", Some(&[0]), Some(5..10))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(ids(&rows), (5..10).collect::<Vec<_>>());
+
+    assert!(cache
+        .get_or_decode(&source, "missing.arrow", None, None)
+        .await
+        .unwrap()
+        .is_none());
+    let err = cache
+        .get_or_decode(&source, "bad.arrow", None, None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, CacheError::Decode { .. }), "{err}");
+    assert!(cache.get("bad.arrow", None, None).await.is_none());
+}
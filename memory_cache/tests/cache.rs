@@ -0,0 +1,309 @@
+use bytes::Bytes;
+use memory_cache::{CacheError, FileCache};
+
+const MB: usize = 1024 * 1024;
+
+async fn set_and_get<C: FileCache>(cache: C) {
+    let data = Bytes::from("0123456789");
+    assert!(cache.get("a.parquet").await.is_none());
+    cache.set("test", "a.parquet", data.clone()).await.unwrap();
+    assert!(cache.exist("a.parquet").await);
+    assert_eq!(cache.get("a.parquet").await, Some(data));
+    assert_eq!(
+        cache.get_range("a.parquet", 2..5).await,
+        Some(Bytes::from("234"))
+    );
+    assert_eq!(cache.get_range("a.parquet", 5..11).await, None);
+    assert_eq!(cache.get_range("b.parquet", 0..1).await, None);
+    assert_eq!(cache.len().await, 1);
+}
+
+async fn set_existing_is_noop<C: FileCache>(cache: C) {
+    cache.set("test", "a", Bytes::from("first")).await.unwrap();
+    cache.set("test", "a", Bytes::from("second")).await.unwrap();
+    assert_eq!(cache.get("a").await, Some(Bytes::from("first")));
+    let stats = cache.stats().await;
+    assert_eq!(stats.entries, 1);
+    assert_eq!(stats.cur_size, "a".len() + "first".len());
+}
+
+async fn remove<C: FileCache>(cache: C) {
+    cache.set("test", "a", Bytes::from("aaaa")).await.unwrap();
+    cache.set("test", "b", Bytes::from("bbbb")).await.unwrap();
+    assert!(cache.remove("a").await);
+    assert!(!cache.remove("a").await);
+    assert!(!cache.exist("a").await);
+    assert!(cache.get("a").await.is_none());
+    let stats = cache.stats().await;
+    assert_eq!(stats.entries, 1);
+    assert_eq!(stats.cur_size, 5);
+    assert!(cache.remove("b").await);
+    assert!(cache.is_empty().await);
+    assert_eq!(cache.stats().await.cur_size, 0);
+}
+
+async fn remove_prefix_and_clear<C: FileCache>(cache: C) {
+    for file in ["org1/logs/a", "org1/logs/b", "org1/traces/a", "org2/logs/a"] {
+        cache.set("test", file, Bytes::from("data")).await.unwrap();
+    }
+    assert_eq!(cache.remove_prefix("org1/logs/").await, 2);
+    assert_eq!(cache.remove_prefix("org1/logs/").await, 0);
+    assert!(!cache.exist("org1/logs/a").await);
+    assert!(cache.exist("org1/traces/a").await);
+    let stats = cache.stats().await;
+    assert_eq!(stats.entries, 2);
+    assert_eq!(
+        stats.cur_size,
+        "org1/traces/a".len() + "org2/logs/a".len() + 8
+    );
+
+    cache.clear().await;
+    assert!(cache.is_empty().await);
+    assert!(!cache.exist("org2/logs/a").await);
+    assert_eq!(cache.stats().await.cur_size, 0);
+    // the cache keeps working after being cleared
+    cache
+        .set("test", "org1/logs/a", Bytes::from("data"))
+        .await
+        .unwrap();
+    assert_eq!(cache.get("org1/logs/a").await, Some(Bytes::from("data")));
+}
+
+async fn evict_when_full<C: FileCache>(cache: C) {
+    let data = Bytes::from(vec![0u8; 1024]);
+    for i in 0..100 {
+        cache
+            .set("test", &format!("file-{i:03}"), data.clone())
+            .await
+            .unwrap();
+    }
+    let stats = cache.stats().await;
+    assert!(stats.cur_size <= stats.max_size);
+    assert!(stats.entries < 100);
+    assert_eq!(stats.entries, cache.len().await);
+    assert_eq!(stats.cur_size, stats.entries * ("file-000".len() + 1024));
+    assert!(cache.exist("file-099").await);
+    assert!(!cache.exist("file-000").await);
+}
+
+async fn entry_too_large<C: FileCache>(cache: C) {
+    cache
+        .set("test", "small", Bytes::from("data"))
+        .await
+        .unwrap();
+    let err = cache
+        .set("test", "big", Bytes::from(vec![0u8; 32 * 1024]))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        CacheError::EntryTooLarge { size, max_size, .. } if size == 32 * 1024 + 3 && max_size == 32 * 1024
+    ));
+    // the rejected entry does not push anything out
+    assert!(!cache.exist("big").await);
+    assert!(cache.exist("small").await);
+    assert_eq!(cache.stats().await.evictions, 0);
+}
+
+async fn instances_are_independent<C: FileCache + Clone>(a: C, b: C) {
+    a.set("test", "a", Bytes::from("aaaa")).await.unwrap();
+    assert!(a.exist("a").await);
+    assert!(!b.exist("a").await);
+    let a2 = a.clone();
+    assert!(a2.exist("a").await);
+    a2.remove("a").await;
+    assert!(!a.exist("a").await);
+}
+
+async fn eviction_policy<C: FileCache>(cache: C, keeps_accessed: bool) {
+    let data = Bytes::from("0123456789");
+    for i in 0..600 {
+        cache
+            .set("test", &format!("k{i:03}"), data.clone())
+            .await
+            .unwrap();
+    }
+    assert!(cache.get("k000").await.is_some());
+    for i in 600..800 {
+        cache
+            .set("test", &format!("k{i:03}"), data.clone())
+            .await
+            .unwrap();
+    }
+    assert!(!cache.exist("k001").await);
+    assert_eq!(cache.exist("k000").await, keeps_accessed);
+}
+
+async fn stats_counters<C: FileCache>(cache: C) {
+    let data = Bytes::from(vec![0u8; 1024]);
+    cache.set("test", "a", data.clone()).await.unwrap();
+    cache.set("test", "a", data.clone()).await.unwrap();
+    assert!(cache.get("a").await.is_some());
+    assert!(cache.get_range("a", 0..10).await.is_some());
+    assert!(cache.get_range("a", 0..2048).await.is_none());
+    assert!(cache.get("b").await.is_none());
+    let stats = cache.stats().await;
+    assert_eq!(stats.inserts, 1);
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.range_hits, 1);
+    assert_eq!(stats.misses, 2);
+    assert_eq!(stats.evictions, 0);
+    assert_eq!(stats.hit_ratio(), 0.5);
+
+    for i in 0..100 {
+        cache
+            .set("test", &format!("file-{i:03}"), data.clone())
+            .await
+            .unwrap();
+    }
+    let stats = cache.stats().await;
+    assert_eq!(stats.inserts, 101);
+    assert!(stats.evictions > 0);
+    assert_eq!(stats.inserts - stats.evictions, stats.entries as u64);
+    let text = memory_cache::encode_prometheus(&[stats]);
+    assert!(text.contains("memory_cache_inserts_total{cache=\"test\"} 101"));
+}
+
+async fn set_capacity<C: FileCache>(cache: C) {
+    let data = Bytes::from(vec![0u8; 1024]);
+    for i in 0..16 {
+        cache
+            .set("test", &format!("file-{i:03}"), data.clone())
+            .await
+            .unwrap();
+    }
+    let entry_size = "file-000".len() + 1024;
+    assert_eq!(cache.stats().await.cur_size, 16 * entry_size);
+
+    // shrinking drops the coldest entries right away
+    cache.set_capacity(4 * entry_size).await;
+    let stats = cache.stats().await;
+    assert_eq!(stats.max_size, 4 * entry_size);
+    assert_eq!(stats.entries, 4);
+    assert_eq!(stats.evictions, 12);
+    assert!(!cache.exist("file-011").await);
+    assert!(cache.exist("file-012").await);
+    assert!(cache.exist("file-015").await);
+
+    // growing keeps everything and makes room again
+    cache.set_capacity(32 * 1024).await;
+    for i in 16..24 {
+        cache
+            .set("test", &format!("file-{i:03}"), data.clone())
+            .await
+            .unwrap();
+    }
+    let stats = cache.stats().await;
+    assert_eq!(stats.entries, 12);
+    assert_eq!(stats.evictions, 12);
+}
+
+async fn memory_watcher<C: FileCache>(cache: C, watcher: tokio::task::JoinHandle<()>) {
+    let data = Bytes::from(vec![0u8; 1024]);
+    for i in 0..16 {
+        cache
+            .set("test", &format!("file-{i:03}"), data.clone())
+            .await
+            .unwrap();
+    }
+    // the process is always above a high-water mark of one byte
+    for _ in 0..100 {
+        if cache.stats().await.max_size == 4 * 1024 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let stats = cache.stats().await;
+    assert_eq!(stats.max_size, 4 * 1024);
+    assert!(stats.cur_size <= 4 * 1024);
+
+    drop(cache);
+    tokio::time::timeout(std::time::Duration::from_secs(1), watcher)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+macro_rules! backend_tests {
+    ($($backend:ident),*) => {$(
+        mod $backend {
+            use memory_cache::$backend::FileCache as Backend;
+
+            #[tokio::test]
+            async fn set_and_get() {
+                super::set_and_get(Backend::new("test")).await;
+            }
+
+            #[tokio::test]
+            async fn set_existing_is_noop() {
+                super::set_existing_is_noop(Backend::new("test")).await;
+            }
+
+            #[tokio::test]
+            async fn remove() {
+                super::remove(Backend::new("test")).await;
+            }
+
+            #[tokio::test]
+            async fn remove_prefix_and_clear() {
+                super::remove_prefix_and_clear(Backend::new("test")).await;
+            }
+
+            #[tokio::test]
+            async fn evict_when_full() {
+                super::evict_when_full(Backend::with_capacity("test", 32 * 1024, 4 * 1024)).await;
+            }
+
+            #[tokio::test]
+            async fn entry_too_large() {
+                super::entry_too_large(Backend::with_capacity("test", 32 * 1024, 4 * 1024)).await;
+            }
+
+            #[tokio::test]
+            async fn stats_counters() {
+                super::stats_counters(Backend::with_capacity("test", 32 * 1024, 4 * 1024)).await;
+            }
+
+            #[tokio::test]
+            async fn set_capacity() {
+                super::set_capacity(Backend::with_capacity("test", 32 * 1024, 4 * 1024)).await;
+            }
+
+            #[tokio::test]
+            async fn memory_watcher() {
+                use memory_cache::PressureConfig;
+                let cache = Backend::with_capacity("test", 32 * 1024, 4 * 1024);
+                let mut cfg = PressureConfig::new(1);
+                cfg.interval = std::time::Duration::from_millis(10);
+                cfg.min_size = 4 * 1024;
+                let watcher = cache.start_memory_watcher(cfg);
+                super::memory_watcher(cache, watcher).await;
+            }
+
+            #[tokio::test]
+            async fn eviction_policy() {
+                use memory_cache::{CacheConfig, Policy};
+                for (policy, keeps_accessed) in [
+                    (Policy::Lru, true),
+                    (Policy::Fifo, false),
+                    (Policy::Lfu, true),
+                    (Policy::S3Fifo, true),
+                ] {
+                    let cfg = CacheConfig::with_capacity("test", 10_000, 0).with_policy(policy);
+                    super::eviction_policy(Backend::with_config(cfg), keeps_accessed).await;
+                }
+            }
+
+            #[tokio::test]
+            async fn instances_are_independent() {
+                super::instances_are_independent(
+                    Backend::with_capacity("parquet", super::MB, super::MB / 10),
+                    Backend::with_capacity("index", super::MB, super::MB / 10),
+                )
+                .await;
+            }
+        }
+    )*};
+}
+
+backend_tests!(memory_v1);
@@ -0,0 +1,112 @@
+use arrow::{
+    array::{Int32Array, StringArray},
+    datatypes::{DataType, Field, Schema},
+    ipc::writer::{FileWriter, StreamWriter},
+    record_batch::RecordBatch,
+};
+use std::{path::Path, process::Command, sync::Arc};
+
+fn batches() -> Vec<RecordBatch> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
+        Field::new("name", DataType::Utf8, true),
+    ]));
+    (0..2)
+        .map(|b| {
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from(vec![b * 3, b * 3 + 1, b * 3 + 2])),
+                    Arc::new(StringArray::from(vec![Some("a"), None, Some("c")])),
+                ],
+            )
+            .unwrap()
+        })
+        .collect()
+}
+
+fn write_stream(path: &Path) {
+    let batches = batches();
+    let file = std::fs::File::create(path).unwrap();
+    let mut writer = StreamWriter::try_new(file, &batches[0].schema()).unwrap();
+    for batch in batches.iter() {
+        writer.write(batch).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+fn write_file(path: &Path) {
+    let batches = batches();
+    let file = std::fs::File::create(path).unwrap();
+    let mut writer = FileWriter::try_new(file, &batches[0].schema()).unwrap();
+    for batch in batches.iter() {
+        writer.write(batch).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+fn run(args: &[&str]) -> (bool, String, String) {
+    let out = Command::new(env!("CARGO_BIN_EXE_memory_cache"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        out.status.success(),
+        String::from_utf8(out.stdout).unwrap(),
+        String::from_utf8(out.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn inspect_stream_and_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let stream = dir.path().join("data.arrows");
+    let file = dir.path().join("data.arrow");
+    write_stream(&stream);
+    write_file(&file);
+
+    for path in [&stream, &file] {
+        let path = path.to_str().unwrap();
+
+        let (ok, out, _) = run(&["schema", path]);
+        assert!(ok);
+        assert_eq!(out, "id: Int32 not null\nname: Utf8\n");
+
+        let (ok, out, _) = run(&["head", "-n", "4", "--format", "csv", path]);
+        assert!(ok);
+        assert_eq!(out, "id,name\n0,a\n1,\n2,c\n3,a\n");
+
+        let (ok, out, _) = run(&["cat", "--format", "json", path]);
+        assert!(ok);
+        assert_eq!(out.lines().count(), 6);
+        assert_eq!(out.lines().next().unwrap(), r#"{"id":0,"name":"a"}"#);
+
+        let (ok, out, _) = run(&["cat", path]);
+        assert!(ok);
+        assert!(out.starts_with("+----+------+"), "{out}");
+
+        let (ok, out, _) = run(&["stats", path]);
+        assert!(ok);
+        assert!(out.starts_with("rows: 6\nbatches: 2\n"), "{out}");
+        let name = out.lines().find(|l| l.starts_with("name")).unwrap();
+        assert_eq!(name.split_whitespace().nth(1), Some("2"));
+    }
+}
+
+#[test]
+fn report_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.arrow");
+    let (ok, _, err) = run(&["schema", missing.to_str().unwrap()]);
+    assert!(!ok);
+    assert!(err.starts_with("Error: Failed to open file"), "{err}");
+
+    let garbage = dir.path().join("garbage.arrow");
+    std::fs::write(&garbage, b"not arrow at all").unwrap();
+    let (ok, _, err) = run(&["cat", garbage.to_str().unwrap()]);
+    assert!(!ok);
+    assert!(
+        err.starts_with("Error: Failed to read Arrow data from file"),
+        "{err}"
+    );
+}
@@ -0,0 +1,170 @@
+use bytes::Bytes;
+use memory_cache::{CacheConfig, DiskConfig, FileCache};
+
+const ENTRY_SIZE: usize = 1024;
+
+fn config(dir: &std::path::Path, disk_size: usize) -> CacheConfig {
+    let mut cfg = CacheConfig::with_capacity("disk", 8 * ENTRY_SIZE, ENTRY_SIZE);
+    cfg.disk = Some(DiskConfig {
+        dir: dir.to_path_buf(),
+        max_size: disk_size,
+        promote_hits: 2,
+    });
+    cfg
+}
+
+fn entry(i: usize) -> (String, Bytes) {
+    (format!("f{i:03}"), Bytes::from(vec![i as u8; ENTRY_SIZE]))
+}
+
+async fn spill_and_read_back<C: FileCache>(cache: C) {
+    for i in 0..32 {
+        let (file, data) = entry(i);
+        cache.set("test", &file, data).await.unwrap();
+    }
+    let stats = cache.stats().await;
+    assert!(stats.cur_size <= stats.max_size);
+    assert!(stats.disk_entries > 0);
+    assert_eq!(stats.entries + stats.disk_entries, 32);
+
+    // the first file was evicted from memory long ago
+    let (file, data) = entry(0);
+    assert!(cache.exist(&file).await);
+    assert_eq!(
+        cache.get_range(&file, 10..20).await,
+        Some(data.slice(10..20))
+    );
+    assert_eq!(cache.get_range(&file, 10..2000).await, None);
+    assert_eq!(cache.stats().await.disk_entries, stats.disk_entries);
+
+    // second hit promotes the file back into memory, pushing others out
+    assert_eq!(cache.get(&file).await, Some(data.clone()));
+    let after = cache.stats().await;
+    assert_eq!(after.entries + after.disk_entries, 32);
+    assert!(after.entries >= 1);
+    assert_eq!(cache.get(&file).await, Some(data));
+}
+
+async fn disk_budget<C: FileCache>(cache: C) {
+    for i in 0..64 {
+        let (file, data) = entry(i);
+        cache.set("test", &file, data).await.unwrap();
+    }
+    let stats = cache.stats().await;
+    assert!(stats.disk_size <= 16 * (ENTRY_SIZE + 4));
+    assert!(stats.entries + stats.disk_entries < 64);
+    assert!(!cache.exist(&entry(0).0).await);
+    assert!(cache.get(&entry(0).0).await.is_none());
+}
+
+async fn remove_from_disk<C: FileCache>(cache: C) {
+    for i in 0..32 {
+        let (file, data) = entry(i);
+        cache.set("test", &file, data).await.unwrap();
+    }
+    let before = cache.stats().await.disk_entries;
+    assert!(cache.remove(&entry(0).0).await);
+    assert!(!cache.exist(&entry(0).0).await);
+    assert_eq!(cache.stats().await.disk_entries, before - 1);
+}
+
+async fn remove_prefix_from_disk<C: FileCache>(cache: C, dir: &std::path::Path) {
+    for i in 0..32 {
+        let (file, data) = entry(i);
+        cache.set("test", &file, data).await.unwrap();
+    }
+    let stats = cache.stats().await;
+    assert!(stats.disk_entries > 10);
+    // f000 - f009 were spilled to disk
+    assert_eq!(cache.remove_prefix("f00").await, 10);
+    assert!(!cache.exist("f005").await);
+    assert!(cache.exist("f015").await);
+    assert_eq!(cache.stats().await.disk_entries, stats.disk_entries - 10);
+
+    cache.clear().await;
+    let stats = cache.stats().await;
+    assert_eq!(
+        (stats.entries, stats.disk_entries, stats.disk_size),
+        (0, 0, 0)
+    );
+    assert_eq!(
+        std::fs::read_dir(dir.join("memory_cache-disk"))
+            .unwrap()
+            .count(),
+        0
+    );
+}
+
+async fn owned_dir<C: FileCache>(cache: C, dir: &std::path::Path) {
+    // only the files in the directory of the cache are its own
+    assert!(dir.join("other.cache").exists());
+    assert!(dir.join("memory_cache-disk/1.cache").exists());
+    for i in 0..32 {
+        let (file, data) = entry(i);
+        cache.set("test", &file, data).await.unwrap();
+    }
+    assert!(dir.join("other.cache").exists());
+    assert!(dir.join("memory_cache-other/1.cache").exists());
+    // the leftover was dropped before spilled files took its name
+    assert_eq!(
+        std::fs::read(dir.join("memory_cache-disk/1.cache")).unwrap(),
+        entry(0).1
+    );
+    let stats = cache.stats().await;
+    assert_eq!(
+        std::fs::read_dir(dir.join("memory_cache-disk"))
+            .unwrap()
+            .count(),
+        stats.disk_entries
+    );
+}
+
+macro_rules! backend_tests {
+    ($($backend:ident),*) => {$(
+        mod $backend {
+            use memory_cache::$backend::FileCache as Backend;
+
+            #[tokio::test]
+            async fn spill_and_read_back() {
+                let dir = tempfile::tempdir().unwrap();
+                let cfg = super::config(dir.path(), 1024 * super::ENTRY_SIZE);
+                super::spill_and_read_back(Backend::with_config(cfg)).await;
+            }
+
+            #[tokio::test]
+            async fn disk_budget() {
+                let dir = tempfile::tempdir().unwrap();
+                let cfg = super::config(dir.path(), 16 * (super::ENTRY_SIZE + 4));
+                super::disk_budget(Backend::with_config(cfg)).await;
+            }
+
+            #[tokio::test]
+            async fn remove_from_disk() {
+                let dir = tempfile::tempdir().unwrap();
+                let cfg = super::config(dir.path(), 1024 * super::ENTRY_SIZE);
+                super::remove_from_disk(Backend::with_config(cfg)).await;
+            }
+
+            #[tokio::test]
+            async fn remove_prefix_from_disk() {
+                let dir = tempfile::tempdir().unwrap();
+                let cfg = super::config(dir.path(), 1024 * super::ENTRY_SIZE);
+                super::remove_prefix_from_disk(Backend::with_config(cfg), dir.path()).await;
+            }
+
+            #[tokio::test]
+            async fn owned_dir() {
+                let dir = tempfile::tempdir().unwrap();
+                for path in ["other.cache", "memory_cache-disk/1.cache", "memory_cache-other/1.cache"] {
+                    let path = dir.path().join(path);
+                    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                    std::fs::write(path, "left behind").unwrap();
+                }
+                let cfg = super::config(dir.path(), 1024 * super::ENTRY_SIZE);
+                super::owned_dir(Backend::with_config(cfg), dir.path()).await;
+            }
+        }
+    )*};
+}
+
+backend_tests!(memory_v1);
@@ -0,0 +1,302 @@
+//! Property-based tests of the bookkeeping of the backends. Operation
+//! sequences are generated by proptest and checked with `verify()` after
+//! every step, a failure is shrunk to a minimal sequence.
+//!
+//! Concurrent tasks run on a single threaded runtime and yield where proptest
+//! decides, so every interleaving replays exactly and shrinks too. This
+//! samples interleavings at random, it doesn't enumerate them like a model
+//! checker.
+
+use bytes::Bytes;
+use memory_cache::{CacheConfig, FileCache, Policy};
+use proptest::{
+    collection::vec,
+    prelude::*,
+    strategy::ValueTree,
+    test_runner::{Config, TestRunner},
+};
+use std::time::Duration;
+
+const FILES: usize = 200;
+const MAX_SIZE: usize = 32 * 1024;
+const POLICIES: [Policy; 4] = [Policy::Lru, Policy::Fifo, Policy::Lfu, Policy::S3Fifo];
+
+fn file(i: usize) -> String {
+    format!("f{i:03}")
+}
+
+/// The content of a file is derived from its name, so every read can be
+/// checked.
+fn data(i: usize) -> Bytes {
+    Bytes::from(vec![i as u8; 64 + i * 37 % 512])
+}
+
+fn config(policy: Policy) -> CacheConfig {
+    CacheConfig::with_capacity("test", MAX_SIZE, 2 * 1024).with_policy(policy)
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    Set(usize),
+    SetWithTtl(usize, Duration),
+    Get(usize),
+    /// Bounds of the range, taken modulo the length of the file.
+    GetRange(usize, usize, usize),
+    Remove(usize),
+    RemovePrefix(usize),
+    SetCapacity(usize),
+    RemoveExpired,
+    Advance(Duration),
+    Clear,
+}
+
+fn policy() -> impl Strategy<Value = Policy> {
+    proptest::sample::select(POLICIES.to_vec())
+}
+
+fn millis(range: std::ops::Range<u64>) -> impl Strategy<Value = Duration> {
+    range.prop_map(Duration::from_millis)
+}
+
+fn capacity() -> impl Strategy<Value = usize> {
+    MAX_SIZE / 4..=MAX_SIZE
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        30 => (0..FILES).prop_map(Op::Set),
+        10 => (0..FILES, millis(1..50)).prop_map(|(i, ttl)| Op::SetWithTtl(i, ttl)),
+        25 => (0..FILES).prop_map(Op::Get),
+        10 => (0..FILES, any::<usize>(), any::<usize>())
+            .prop_map(|(i, a, b)| Op::GetRange(i, a, b)),
+        10 => (0..FILES).prop_map(Op::Remove),
+        3 => (0..2usize).prop_map(Op::RemovePrefix),
+        4 => capacity().prop_map(Op::SetCapacity),
+        4 => Just(Op::RemoveExpired),
+        3 => millis(1..20).prop_map(Op::Advance),
+        1 => Just(Op::Clear),
+    ]
+}
+
+fn ops() -> impl Strategy<Value = Vec<Op>> {
+    vec(op(), 1..1000)
+}
+
+/// Runs one operation, checking what can be known without a model of the
+/// eviction policy.
+async fn apply<C: FileCache>(cache: &C, op: &Op, ctx: &str) {
+    match *op {
+        Op::Set(i) => {
+            cache.set("test", &file(i), data(i)).await.unwrap();
+            assert!(cache.exist(&file(i)).await, "{ctx}: missing after set");
+        }
+        Op::SetWithTtl(i, ttl) => {
+            cache
+                .set_with_ttl("test", &file(i), data(i), ttl)
+                .await
+                .unwrap();
+        }
+        Op::Get(i) => {
+            if let Some(got) = cache.get(&file(i)).await {
+                assert_eq!(got, data(i), "{ctx}: wrong data");
+            }
+        }
+        Op::GetRange(i, a, b) => {
+            let data = data(i);
+            let (a, b) = (a % (data.len() + 1), b % (data.len() + 1));
+            let range = a.min(b)..a.max(b);
+            if let Some(got) = cache.get_range(&file(i), range.clone()).await {
+                assert_eq!(got, data.slice(range), "{ctx}: wrong range");
+            }
+        }
+        Op::Remove(i) => {
+            cache.remove(&file(i)).await;
+            assert!(!cache.exist(&file(i)).await, "{ctx}: left after remove");
+        }
+        Op::RemovePrefix(d) => {
+            cache.remove_prefix(&format!("f{d}")).await;
+        }
+        Op::SetCapacity(max_size) => {
+            cache.set_capacity(max_size).await;
+        }
+        Op::RemoveExpired => {
+            cache.remove_expired().await;
+        }
+        Op::Advance(duration) => {
+            tokio::time::advance(duration).await;
+        }
+        Op::Clear => {
+            cache.clear().await;
+            assert!(cache.is_empty().await, "{ctx}: entries left after clear");
+        }
+    }
+}
+
+async fn verify<C: FileCache>(cache: &C, ctx: &str) {
+    if let Err(e) = cache.verify().await {
+        panic!("{ctx}: {e}");
+    }
+}
+
+fn random_ops<C: FileCache>(cache: C, ops: &[Op]) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .start_paused(true)
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        for (n, op) in ops.iter().enumerate() {
+            let ctx = format!("step {n} {op:?}");
+            apply(&cache, op, &ctx).await;
+            verify(&cache, &ctx).await;
+            let stats = cache.stats().await;
+            assert_eq!(stats.entries, cache.len().await, "{ctx}");
+        }
+    });
+}
+
+#[derive(Clone, Debug)]
+enum TaskOp {
+    Set(usize),
+    Get(usize),
+    Remove(usize),
+    SetCapacity(usize),
+}
+
+/// An operation of a task and whether the task yields after it.
+fn task_op() -> impl Strategy<Value = (TaskOp, bool)> {
+    let op = prop_oneof![
+        4 => (0..FILES).prop_map(TaskOp::Set),
+        4 => (0..FILES).prop_map(TaskOp::Get),
+        1 => (0..FILES).prop_map(TaskOp::Remove),
+        1 => capacity().prop_map(TaskOp::SetCapacity),
+    ];
+    (op, proptest::bool::weighted(0.3))
+}
+
+fn tasks(tasks: usize, steps: usize) -> impl Strategy<Value = Vec<Vec<(TaskOp, bool)>>> {
+    vec(vec(task_op(), 1..=steps), tasks)
+}
+
+async fn worker<C: FileCache>(cache: C, task: usize, ops: Vec<(TaskOp, bool)>) {
+    for (n, (op, yields)) in ops.into_iter().enumerate() {
+        let ctx = format!("task {task} step {n} {op:?}");
+        match op {
+            TaskOp::Set(i) => {
+                if let Err(e) = cache.set("test", &file(i), data(i)).await {
+                    panic!("{ctx}: {e}");
+                }
+            }
+            TaskOp::Get(i) => {
+                if let Some(got) = cache.get(&file(i)).await {
+                    assert_eq!(got, data(i), "{ctx}: wrong data");
+                }
+            }
+            TaskOp::Remove(i) => {
+                cache.remove(&file(i)).await;
+            }
+            TaskOp::SetCapacity(max_size) => {
+                cache.set_capacity(max_size).await;
+            }
+        }
+        if yields {
+            tokio::task::yield_now().await;
+        }
+    }
+}
+
+async fn concurrent_ops<C: FileCache + Clone>(cache: C, tasks: Vec<Vec<(TaskOp, bool)>>) {
+    let checks = tasks.iter().map(Vec::len).max().unwrap_or(0) / 10;
+    let handles = tasks
+        .into_iter()
+        .enumerate()
+        .map(|(task, ops)| tokio::spawn(worker(cache.clone(), task, ops)))
+        .collect::<Vec<_>>();
+    // the bookkeeping holds at any point in between
+    for _ in 0..checks {
+        verify(&cache, "while running").await;
+        tokio::task::yield_now().await;
+    }
+    for handle in handles {
+        handle.await.unwrap();
+    }
+    verify(&cache, "after running").await;
+}
+
+/// Single threaded runs are deterministic, the same tasks always interleave
+/// the same way.
+fn interleavings<C: FileCache + Clone>(cache: C, tasks: Vec<Vec<(TaskOp, bool)>>) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(concurrent_ops(cache, tasks));
+}
+
+/// Tasks for a run which can't be replayed anyway, drawn without shrinking.
+fn sample_tasks(tasks_len: usize, steps: usize) -> Vec<Vec<(TaskOp, bool)>> {
+    let mut runner = TestRunner::new(Config::default());
+    tasks(tasks_len, steps)
+        .new_tree(&mut runner)
+        .unwrap()
+        .current()
+}
+
+macro_rules! backend_tests {
+    ($($backend:ident),*) => {$(
+        mod $backend {
+            use memory_cache::$backend::FileCache as Backend;
+            use proptest::prelude::*;
+
+            fn build(policy: memory_cache::Policy) -> Backend {
+                Backend::with_config(super::config(policy))
+            }
+
+            proptest! {
+                #![proptest_config(ProptestConfig::with_cases(32))]
+
+                #[test]
+                fn random_ops(policy in super::policy(), ops in super::ops()) {
+                    super::random_ops(build(policy), &ops);
+                }
+
+                #[test]
+                fn interleavings(policy in super::policy(), tasks in super::tasks(8, 200)) {
+                    super::interleavings(build(policy), tasks);
+                }
+            }
+
+            #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+            async fn parallel_ops() {
+                for policy in super::POLICIES {
+                    super::concurrent_ops(build(policy), super::sample_tasks(8, 2000)).await;
+                }
+            }
+        }
+    )*};
+}
+
+backend_tests!(memory_v1);
+
+mod sharded {
+    use memory_cache::{memory_v1, Policy, ShardedCache};
+    use proptest::prelude::*;
+
+    fn build(policy: Policy) -> ShardedCache<memory_v1::FileCache> {
+        ShardedCache::with_config(super::config(policy), 4, memory_v1::FileCache::with_config)
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn random_ops(policy in super::policy(), ops in super::ops()) {
+            super::random_ops(build(policy), &ops);
+        }
+
+        #[test]
+        fn interleavings(policy in super::policy(), tasks in super::tasks(8, 200)) {
+            super::interleavings(build(policy), tasks);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use bytes::Bytes;
+use memory_cache::{CacheError, FileCache};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+async fn load_once<C: FileCache + Clone>(cache: C) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut tasks = Vec::new();
+    for _ in 0..16 {
+        let cache = cache.clone();
+        let calls = calls.clone();
+        tasks.push(tokio::spawn(async move {
+            cache
+                .get_or_load("a", move || async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok(Bytes::from("aaaa"))
+                })
+                .await
+        }));
+    }
+    for task in tasks {
+        assert_eq!(task.await.unwrap().unwrap(), Bytes::from("aaaa"));
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(cache.get("a").await, Some(Bytes::from("aaaa")));
+
+    // a cold load counts a single miss and no hit
+    let before = cache.stats().await;
+    let data = cache
+        .get_or_load("b", || async { Ok(Bytes::from("bbbb")) })
+        .await
+        .unwrap();
+    assert_eq!(data, Bytes::from("bbbb"));
+    let stats = cache.stats().await;
+    assert_eq!(stats.misses, before.misses + 1);
+    assert_eq!(stats.hits, before.hits);
+
+    // cached files never run the loader
+    let data = cache
+        .get_or_load("a", || async { panic!("loader called for a cached file") })
+        .await
+        .unwrap();
+    assert_eq!(data, Bytes::from("aaaa"));
+}
+
+async fn load_error<C: FileCache + Clone>(cache: C) {
+    let mut tasks = Vec::new();
+    for _ in 0..4 {
+        let cache = cache.clone();
+        tasks.push(tokio::spawn(async move {
+            cache
+                .get_or_load("a", || async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Err("object store unavailable".into())
+                })
+                .await
+        }));
+    }
+    for task in tasks {
+        let err = task.await.unwrap().unwrap_err();
+        assert!(matches!(err, CacheError::LoaderFailed { .. }));
+        assert!(err.to_string().contains("object store unavailable"));
+    }
+    assert!(!cache.exist("a").await);
+
+    // the failure is not remembered, the next caller loads again
+    let data = cache
+        .get_or_load("a", || async { Ok(Bytes::from("aaaa")) })
+        .await
+        .unwrap();
+    assert_eq!(data, Bytes::from("aaaa"));
+    assert!(cache.exist("a").await);
+}
+
+async fn load_panic<C: FileCache + Clone>(cache: C) {
+    let mut tasks = Vec::new();
+    for _ in 0..4 {
+        let cache = cache.clone();
+        tasks.push(tokio::spawn(async move {
+            cache
+                .get_or_load("a", || async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    panic!("object store client bug")
+                })
+                .await
+        }));
+    }
+    for task in tasks {
+        let err = task.await.unwrap().unwrap_err();
+        assert!(matches!(err, CacheError::LoaderFailed { .. }));
+        assert!(err.to_string().contains("object store client bug"));
+    }
+    assert!(!cache.exist("a").await);
+
+    // the panicked load is dropped, the next caller loads again
+    let data = cache
+        .get_or_load("a", || async { Ok(Bytes::from("aaaa")) })
+        .await
+        .unwrap();
+    assert_eq!(data, Bytes::from("aaaa"));
+}
+
+async fn load_nested<C: FileCache + Clone>(cache: C) {
+    // the loader is called without holding any lock of the running loads
+    let inner = cache.clone();
+    let data = cache
+        .get_or_load("a", move || {
+            let nested = inner.get_or_load("b", || async { Ok(Bytes::from("bbbb")) });
+            let data = futures::executor::block_on(nested).unwrap();
+            async move { Ok(data) }
+        })
+        .await
+        .unwrap();
+    assert_eq!(data, Bytes::from("bbbb"));
+    assert!(cache.exist("a").await);
+    assert!(cache.exist("b").await);
+}
+
+macro_rules! backend_tests {
+    ($($backend:ident),*) => {$(
+        mod $backend {
+            use memory_cache::$backend::FileCache as Backend;
+
+            #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+            async fn load_once() {
+                super::load_once(Backend::new("test")).await;
+            }
+
+            #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+            async fn load_error() {
+                super::load_error(Backend::new("test")).await;
+            }
+
+            #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+            async fn load_panic() {
+                super::load_panic(Backend::new("test")).await;
+            }
+
+            #[tokio::test]
+            async fn load_nested() {
+                super::load_nested(Backend::new("test")).await;
+            }
+        }
+    )*};
+}
+
+backend_tests!(memory_v1);
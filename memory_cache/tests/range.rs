@@ -0,0 +1,182 @@
+use bytes::Bytes;
+use memory_cache::{FileCache, RangeCache};
+
+/// Content of the test file, every byte is its offset modulo 251.
+fn content(range: std::ops::Range<usize>) -> Bytes {
+    range.map(|i| (i % 251) as u8).collect::<Vec<_>>().into()
+}
+
+async fn set<C: FileCache>(cache: &RangeCache<C>, range: std::ops::Range<usize>) {
+    cache
+        .set_range("test", "a.parquet", range.start, content(range))
+        .await
+        .unwrap();
+}
+
+async fn missing_ranges<C: FileCache>(cache: RangeCache<C>) {
+    // footer plus two column chunks
+    set(&cache, 9000..10000).await;
+    set(&cache, 1000..2000).await;
+    set(&cache, 4000..5000).await;
+
+    assert_eq!(
+        cache.missing("a.parquet", 0..10000).await,
+        vec![0..1000, 2000..4000, 5000..9000]
+    );
+    assert!(cache.missing("a.parquet", 1200..1800).await.is_empty());
+    assert_eq!(
+        cache.get_range("a.parquet", 1200..1800).await,
+        Some(content(1200..1800))
+    );
+    assert_eq!(cache.get_range("a.parquet", 1500..2500).await, None);
+
+    let lookup = cache.lookup("a.parquet", 1500..4500).await;
+    assert!(!lookup.is_complete());
+    assert_eq!(lookup.missing, vec![2000..4000]);
+    assert_eq!(
+        lookup.pieces,
+        vec![(1500, content(1500..2000)), (4000, content(4000..4500))]
+    );
+    assert_eq!(cache.missing("b.parquet", 0..10).await, vec![0..10]);
+}
+
+async fn merge_ranges<C: FileCache>(cache: RangeCache<C>) {
+    set(&cache, 1000..2000).await;
+    set(&cache, 3000..4000).await;
+    assert_eq!(cache.cache().len().await, 2);
+
+    // adjacent on the left, overlapping on the right
+    set(&cache, 2000..3500).await;
+    assert_eq!(cache.cache().len().await, 1);
+    assert_eq!(
+        cache.get_range("a.parquet", 1000..4000).await,
+        Some(content(1000..4000))
+    );
+    // a range which is already cached changes nothing
+    set(&cache, 1500..2500).await;
+    assert_eq!(cache.cache().len().await, 1);
+    assert_eq!(
+        cache.cache().stats().await.cur_size,
+        "a.parquet\u{0}1000-4000".len() + 3000
+    );
+
+    assert_eq!(cache.remove("a.parquet").await, 1);
+    assert!(cache.cache().is_empty().await);
+    assert_eq!(cache.missing("a.parquet", 0..10).await, vec![0..10]);
+}
+
+async fn assemble_pieces<C: FileCache>(cache: RangeCache<C>) {
+    // pieces are never merged beyond 1000 bytes
+    set(&cache, 0..800).await;
+    set(&cache, 800..1600).await;
+    set(&cache, 500..2000).await;
+    assert_eq!(cache.cache().len().await, 3);
+    assert!(cache.missing("a.parquet", 0..2000).await.is_empty());
+    assert_eq!(
+        cache.get_range("a.parquet", 100..1900).await,
+        Some(content(100..1900))
+    );
+}
+
+async fn split_large_range<C: FileCache>(cache: RangeCache<C>) {
+    set(&cache, 0..2500).await;
+    assert_eq!(cache.cache().len().await, 3);
+    for piece in ["0-1000", "1000-2000", "2000-2500"] {
+        assert!(cache.cache().exist(&format!("a.parquet\u{0}{piece}")).await);
+    }
+    assert_eq!(
+        cache.get_range("a.parquet", 0..2500).await,
+        Some(content(0..2500))
+    );
+
+    // only the uncovered part is stored, split as well
+    set(&cache, 2000..4200).await;
+    assert_eq!(cache.cache().len().await, 5);
+    assert!(cache.missing("a.parquet", 0..4200).await.is_empty());
+    assert_eq!(
+        cache.get_range("a.parquet", 900..4100).await,
+        Some(content(900..4100))
+    );
+}
+
+async fn whole_file<C: FileCache>(cache: RangeCache<C>) {
+    cache
+        .cache()
+        .set("test", "a.parquet", content(0..1000))
+        .await
+        .unwrap();
+    assert!(cache.missing("a.parquet", 100..200).await.is_empty());
+    assert_eq!(
+        cache.get_range("a.parquet", 100..200).await,
+        Some(content(100..200))
+    );
+}
+
+async fn evicted_pieces<C: FileCache>(cache: RangeCache<C>) {
+    for i in 0..8 {
+        set(&cache, i * 4096..i * 4096 + 1024).await;
+    }
+    // the underlying cache made room twice, only the last two pieces are left
+    assert_eq!(cache.cache().len().await, 2);
+    assert_eq!(
+        cache.missing("a.parquet", 0..8 * 4096).await,
+        vec![
+            0..6 * 4096,
+            6 * 4096 + 1024..7 * 4096,
+            7 * 4096 + 1024..8 * 4096
+        ]
+    );
+    assert_eq!(cache.get_range("a.parquet", 0..1024).await, None);
+    // forgotten pieces can be cached again
+    set(&cache, 0..1024).await;
+    assert!(cache.missing("a.parquet", 0..1024).await.is_empty());
+}
+
+macro_rules! backend_tests {
+    ($($backend:ident),*) => {$(
+        mod $backend {
+            use memory_cache::{$backend::FileCache as Backend, RangeCache};
+
+            #[tokio::test]
+            async fn missing_ranges() {
+                super::missing_ranges(RangeCache::new(Backend::new("test"))).await;
+            }
+
+            #[tokio::test]
+            async fn merge_ranges() {
+                super::merge_ranges(RangeCache::new(Backend::new("test"))).await;
+            }
+
+            #[tokio::test]
+            async fn assemble_pieces() {
+                super::assemble_pieces(RangeCache::with_max_piece_size(
+                    Backend::new("test"),
+                    1000,
+                ))
+                .await;
+            }
+
+            #[tokio::test]
+            async fn split_large_range() {
+                super::split_large_range(RangeCache::with_max_piece_size(
+                    Backend::new("test"),
+                    1000,
+                ))
+                .await;
+            }
+
+            #[tokio::test]
+            async fn whole_file() {
+                super::whole_file(RangeCache::new(Backend::new("test"))).await;
+            }
+
+            #[tokio::test]
+            async fn evicted_pieces() {
+                super::evicted_pieces(RangeCache::new(Backend::with_capacity("test", 4096, 1024)))
+                    .await;
+            }
+        }
+    )*};
+}
+
+backend_tests!(memory_v1);
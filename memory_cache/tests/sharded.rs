@@ -0,0 +1,83 @@
+use bytes::Bytes;
+use memory_cache::{CacheConfig, FileCache, ShardedCache};
+
+const ENTRY_SIZE: usize = 1024;
+
+async fn routes_by_file<C: FileCache>(cache: ShardedCache<C>) {
+    assert_eq!(cache.shards().len(), 4);
+    for i in 0..64 {
+        let file = format!("files/{i}.parquet");
+        cache
+            .set("test", &file, Bytes::from(file.clone()))
+            .await
+            .unwrap();
+    }
+    for i in 0..64 {
+        let file = format!("files/{i}.parquet");
+        assert_eq!(cache.get(&file).await, Some(Bytes::from(file.clone())));
+        let mut holders = 0;
+        for shard in cache.shards() {
+            if shard.exist(&file).await {
+                holders += 1;
+            }
+        }
+        assert_eq!(holders, 1);
+    }
+    // every shard got some of the files
+    for shard in cache.shards() {
+        assert!(!shard.is_empty().await);
+    }
+    assert_eq!(cache.len().await, 64);
+    assert!(cache.remove("files/0.parquet").await);
+    assert!(!cache.exist("files/0.parquet").await);
+    assert_eq!(cache.len().await, 63);
+}
+
+async fn budget_per_shard<C: FileCache>(cache: ShardedCache<C>) {
+    let data = Bytes::from(vec![0u8; ENTRY_SIZE]);
+    for i in 0..256 {
+        cache
+            .set("test", &format!("f{i:03}"), data.clone())
+            .await
+            .unwrap();
+    }
+    let stats = cache.stats().await;
+    assert_eq!(stats.name, "sharded");
+    assert_eq!(stats.max_size, 32 * ENTRY_SIZE);
+    assert!(stats.cur_size <= stats.max_size);
+    assert_eq!(stats.inserts, 256);
+    assert!(stats.evictions > 0);
+    assert_eq!(stats.entries, cache.len().await);
+    for shard in cache.shards() {
+        let shard = shard.stats().await;
+        assert_eq!(shard.max_size, 8 * ENTRY_SIZE);
+        assert!(shard.cur_size <= shard.max_size);
+    }
+}
+
+fn config() -> CacheConfig {
+    CacheConfig::with_capacity("sharded", 32 * ENTRY_SIZE, ENTRY_SIZE)
+}
+
+macro_rules! backend_tests {
+    ($($backend:ident),*) => {$(
+        mod $backend {
+            use memory_cache::{$backend::FileCache as Backend, ShardedCache};
+
+            #[tokio::test]
+            async fn routes_by_file() {
+                let cfg = memory_cache::CacheConfig::new("sharded");
+                let cache = ShardedCache::with_config(cfg, 4, Backend::with_config);
+                super::routes_by_file(cache).await;
+            }
+
+            #[tokio::test]
+            async fn budget_per_shard() {
+                let cache = ShardedCache::with_config(super::config(), 4, Backend::with_config);
+                super::budget_per_shard(cache).await;
+            }
+        }
+    )*};
+}
+
+backend_tests!(memory_v1);
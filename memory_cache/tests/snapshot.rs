@@ -0,0 +1,163 @@
+use bytes::Bytes;
+use memory_cache::{CacheConfig, CacheError, FileCache, Policy};
+use std::{path::Path, time::Duration};
+
+const ENTRY_SIZE: usize = 1024;
+
+fn entry(i: usize) -> (String, Bytes) {
+    (format!("f{i:03}"), Bytes::from(vec![i as u8; ENTRY_SIZE]))
+}
+
+fn config(name: &str) -> CacheConfig {
+    CacheConfig::new(name).with_policy(Policy::Lru)
+}
+
+async fn fill<C: FileCache>(cache: &C, n: usize) {
+    for i in 0..n {
+        let (file, data) = entry(i);
+        cache.set("test", &file, data).await.unwrap();
+    }
+}
+
+async fn snapshot_and_restore<C: FileCache>(cache: C, restored: C, path: &Path) {
+    fill(&cache, 8).await;
+    cache
+        .set_with_ttl(
+            "test",
+            "ttl",
+            Bytes::from("data"),
+            Duration::from_secs(3600),
+        )
+        .await
+        .unwrap();
+    // f000 is the hottest entry, f001 the coldest
+    cache.get("f000").await.unwrap();
+
+    assert_eq!(cache.snapshot(path).await.unwrap(), 9);
+    assert_eq!(restored.restore(path, None).await.unwrap(), 9);
+
+    // recency survives the restart
+    let files = |entries: Vec<memory_cache::SnapshotEntry>| {
+        entries.into_iter().map(|e| e.file).collect::<Vec<_>>()
+    };
+    let entries = restored.entries().await;
+    let ttl = entries
+        .iter()
+        .find(|e| e.file == "ttl")
+        .unwrap()
+        .ttl
+        .unwrap();
+    assert!(ttl > Duration::from_secs(3500) && ttl <= Duration::from_secs(3600));
+    let order = files(entries);
+    assert_eq!(order, files(cache.entries().await));
+    assert_eq!(order.first().unwrap(), "f000");
+    assert_eq!(order.last().unwrap(), "f001");
+
+    for i in 0..8 {
+        let (file, data) = entry(i);
+        assert_eq!(restored.get(&file).await, Some(data));
+    }
+    assert_eq!(
+        restored.stats().await.cur_size,
+        cache.stats().await.cur_size
+    );
+}
+
+async fn restore_hottest<C: FileCache>(cache: C, restored: C, path: &Path) {
+    fill(&cache, 8).await;
+    cache.get("f002").await.unwrap();
+    cache.snapshot(path).await.unwrap();
+
+    // only the three hottest entries fit
+    let max_bytes = 3 * ("f000".len() + ENTRY_SIZE) + 10;
+    assert_eq!(restored.restore(path, Some(max_bytes)).await.unwrap(), 3);
+    assert!(restored.exist("f002").await);
+    assert!(restored.exist("f007").await);
+    assert!(restored.exist("f006").await);
+    assert!(!restored.exist("f005").await);
+    assert_eq!(restored.len().await, 3);
+}
+
+async fn reject_corrupt<C: FileCache>(cache: C, restored: C, path: &Path) {
+    fill(&cache, 4).await;
+    cache.snapshot(path).await.unwrap();
+    let mut bytes = std::fs::read(path).unwrap();
+
+    let len = bytes.len();
+    bytes[len - 10] ^= 0xff;
+    std::fs::write(path, &bytes).unwrap();
+    let err = restored.restore(path, None).await.unwrap_err();
+    assert!(matches!(err, CacheError::Snapshot { .. }), "{err}");
+    assert!(err.to_string().contains("checksum mismatch"), "{err}");
+
+    std::fs::write(path, &bytes[..len / 2]).unwrap();
+    let err = restored.restore(path, None).await.unwrap_err();
+    assert!(err.to_string().contains("truncated"), "{err}");
+
+    // lengths which don't fit in the file are rejected before allocating
+    cache.snapshot(path).await.unwrap();
+    let mut huge = std::fs::read(path).unwrap();
+    huge[24..32].copy_from_slice(&u64::MAX.to_le_bytes());
+    std::fs::write(path, &huge).unwrap();
+    let err = restored.restore(path, None).await.unwrap_err();
+    assert!(err.to_string().contains("entry 0 is corrupt"), "{err}");
+    huge[20..24].copy_from_slice(&u32::MAX.to_le_bytes());
+    huge[24..32].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    std::fs::write(path, &huge).unwrap();
+    let err = restored.restore(path, None).await.unwrap_err();
+    assert!(err.to_string().contains("entry 0 is corrupt"), "{err}");
+
+    std::fs::write(path, b"not a snapshot at all").unwrap();
+    let err = restored.restore(path, None).await.unwrap_err();
+    assert!(matches!(err, CacheError::Snapshot { .. }), "{err}");
+
+    let err = restored
+        .restore(&path.with_extension("missing"), None)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, CacheError::Io { .. }), "{err}");
+    assert!(restored.is_empty().await);
+}
+
+macro_rules! backend_tests {
+    ($($backend:ident),*) => {$(
+        mod $backend {
+            use memory_cache::$backend::FileCache as Backend;
+
+            #[tokio::test]
+            async fn snapshot_and_restore() {
+                let dir = tempfile::tempdir().unwrap();
+                super::snapshot_and_restore(
+                    Backend::with_config(super::config("test")),
+                    Backend::with_config(super::config("restored")),
+                    &dir.path().join("cache.snapshot"),
+                )
+                .await;
+            }
+
+            #[tokio::test]
+            async fn restore_hottest() {
+                let dir = tempfile::tempdir().unwrap();
+                super::restore_hottest(
+                    Backend::with_config(super::config("test")),
+                    Backend::with_config(super::config("restored")),
+                    &dir.path().join("cache.snapshot"),
+                )
+                .await;
+            }
+
+            #[tokio::test]
+            async fn reject_corrupt() {
+                let dir = tempfile::tempdir().unwrap();
+                super::reject_corrupt(
+                    Backend::with_config(super::config("test")),
+                    Backend::with_config(super::config("restored")),
+                    &dir.path().join("cache.snapshot"),
+                )
+                .await;
+            }
+        }
+    )*};
+}
+
+backend_tests!(memory_v1);
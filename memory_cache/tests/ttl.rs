@@ -0,0 +1,143 @@
+use bytes::Bytes;
+use memory_cache::{CacheConfig, FileCache};
+use std::time::Duration;
+
+async fn lazy_expiry<C: FileCache>(cache: C) {
+    let data = Bytes::from("0123456789");
+    cache
+        .set_with_ttl("test", "a", data.clone(), Duration::from_secs(10))
+        .await
+        .unwrap();
+    cache.set("test", "b", data.clone()).await.unwrap();
+    tokio::time::advance(Duration::from_secs(5)).await;
+    assert_eq!(cache.get("a").await, Some(data.clone()));
+
+    tokio::time::advance(Duration::from_secs(6)).await;
+    assert!(!cache.exist("a").await);
+    assert!(cache.get("a").await.is_none());
+    assert!(cache.get_range("a", 0..1).await.is_none());
+    assert_eq!(cache.get("b").await, Some(data));
+    let stats = cache.stats().await;
+    assert_eq!(stats.entries, 1);
+    assert_eq!(stats.cur_size, 11);
+}
+
+async fn default_ttl<C: FileCache>(cache: C) {
+    cache.set("test", "a", Bytes::from("old")).await.unwrap();
+    tokio::time::advance(Duration::from_secs(6)).await;
+    // an expired entry does not block caching the file again
+    cache.set("test", "a", Bytes::from("new")).await.unwrap();
+    assert_eq!(cache.get("a").await, Some(Bytes::from("new")));
+    assert_eq!(cache.stats().await.cur_size, 4);
+    tokio::time::advance(Duration::from_secs(6)).await;
+    assert!(cache.get("a").await.is_none());
+    assert!(cache.is_empty().await);
+}
+
+async fn remove_expired<C: FileCache>(cache: C) {
+    let data = Bytes::from("0123456789");
+    for i in 0..10 {
+        let ttl = Duration::from_secs(if i % 2 == 0 { 1 } else { 100 });
+        cache
+            .set_with_ttl("test", &format!("f{i}"), data.clone(), ttl)
+            .await
+            .unwrap();
+    }
+    tokio::time::advance(Duration::from_secs(2)).await;
+    assert_eq!(cache.remove_expired().await, 5);
+    assert_eq!(cache.remove_expired().await, 0);
+    let stats = cache.stats().await;
+    assert_eq!(stats.entries, 5);
+    assert_eq!(stats.cur_size, 5 * 12);
+}
+
+async fn sweeper<C: FileCache>(cache: C, handle: tokio::task::JoinHandle<()>) {
+    let data = Bytes::from("0123456789");
+    for i in 0..10 {
+        let ttl = Duration::from_secs(if i % 2 == 0 { 1 } else { 100 });
+        cache
+            .set_with_ttl("test", &format!("f{i}"), data.clone(), ttl)
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let stats = cache.stats().await;
+    assert_eq!(stats.entries, 5);
+    assert_eq!(stats.cur_size, 5 * 12);
+    drop(cache);
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert!(handle.is_finished());
+}
+
+async fn expiry_on_disk<C: FileCache>(cache: C) {
+    let data = Bytes::from(vec![0u8; 1024]);
+    for i in 0..32 {
+        cache
+            .set_with_ttl(
+                "test",
+                &format!("f{i:02}"),
+                data.clone(),
+                Duration::from_secs(10),
+            )
+            .await
+            .unwrap();
+    }
+    let stats = cache.stats().await;
+    assert!(stats.disk_entries > 0);
+    assert!(cache.exist("f00").await);
+    tokio::time::advance(Duration::from_secs(11)).await;
+    assert!(!cache.exist("f00").await);
+    assert!(cache.get("f00").await.is_none());
+    assert_eq!(
+        cache.remove_expired().await,
+        stats.entries + stats.disk_entries - 1
+    );
+    let stats = cache.stats().await;
+    assert_eq!((stats.entries, stats.disk_entries), (0, 0));
+    assert_eq!((stats.cur_size, stats.disk_size), (0, 0));
+}
+
+macro_rules! backend_tests {
+    ($($backend:ident),*) => {$(
+        mod $backend {
+            use memory_cache::{$backend::FileCache as Backend, CacheConfig};
+            use std::time::Duration;
+
+            #[tokio::test(start_paused = true)]
+            async fn lazy_expiry() {
+                super::lazy_expiry(Backend::new("test")).await;
+            }
+
+            #[tokio::test(start_paused = true)]
+            async fn default_ttl() {
+                let cfg = CacheConfig::new("test").with_ttl(Duration::from_secs(5));
+                super::default_ttl(Backend::with_config(cfg)).await;
+            }
+
+            #[tokio::test(start_paused = true)]
+            async fn remove_expired() {
+                super::remove_expired(Backend::new("test")).await;
+            }
+
+            #[tokio::test(start_paused = true)]
+            async fn sweeper() {
+                let cache = Backend::new("test");
+                let handle = cache.start_sweeper(Duration::from_secs(1));
+                super::sweeper(cache, handle).await;
+            }
+
+            #[tokio::test(start_paused = true)]
+            async fn expiry_on_disk() {
+                let dir = tempfile::tempdir().unwrap();
+                let cfg = super::disk_config(dir.path());
+                super::expiry_on_disk(Backend::with_config(cfg)).await;
+            }
+        }
+    )*};
+}
+
+fn disk_config(dir: &std::path::Path) -> CacheConfig {
+    CacheConfig::with_capacity("test", 8 * 1024, 1024).with_disk(dir, 1024 * 1024)
+}
+
+backend_tests!(memory_v1);
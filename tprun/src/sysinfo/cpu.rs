@@ -15,13 +15,6 @@
 
 use sysinfo::{CpuRefreshKind, ProcessRefreshKind, ProcessesToUpdate, RefreshKind};
 
-// Get number of CPUs
-pub fn get_cpu_num() -> usize {
-    let mut system = sysinfo::System::new();
-    system.refresh_cpu_list(CpuRefreshKind::nothing());
-    system.cpus().len()
-}
-
 // Get average CPU usage
 pub fn get_cpu_usage() -> f32 {
     let mut system = sysinfo::System::new_with_specifics(
@@ -58,11 +51,6 @@ pub fn get_process_cpu_usage() -> f32 {
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_sysinfo_get_cpu_num() {
-        assert!(get_cpu_num() > 0);
-    }
-
     #[test]
     fn test_sysinfo_get_cpu_usage() {
         assert!(get_cpu_usage() > 0.0);